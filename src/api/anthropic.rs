@@ -3,8 +3,10 @@ use reqwest::{blocking::Client, header::CONTENT_TYPE};
 use serde::Deserialize;
 use serde_json::json;
 
-use super::provider::{CommitMessage, ProviderResult};
-use crate::prompt;
+use super::provider::{ProviderResult, ToolSpec};
+
+/// The base URL of the public API.
+const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
 
 /// Structs for deserializing the Anthropic Messages response.
 #[derive(Deserialize)]
@@ -28,13 +30,6 @@ enum ContentBlock {
     },
 }
 
-#[derive(Deserialize)]
-struct CommitMessageToolInput {
-    description: Option<String>,
-    summary: String,
-    warning: Option<String>,
-}
-
 /// Struct for the Anthropic API client.
 pub struct AnthropicApi {
     api_key: String,
@@ -43,7 +38,8 @@ pub struct AnthropicApi {
 }
 
 impl AnthropicApi {
-    pub fn new(api_key: String) -> ProviderResult<Self> {
+    /// Create a client for the API at the given base URL, or the public API by default.
+    pub fn new(api_key: String, base_url: Option<&str>) -> ProviderResult<Self> {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(120))
             .build()
@@ -57,71 +53,62 @@ impl AnthropicApi {
 
         Ok(AnthropicApi {
             api_key,
-            api_url: "https://api.anthropic.com/v1/messages".to_string(),
+            api_url: format!(
+                "{}/messages",
+                base_url.unwrap_or(DEFAULT_BASE_URL).trim_end_matches('/')
+            ),
             client,
         })
     }
 
-    /// Generates a commit message by sending the provided diff to the Anthropic Messages API.
-    pub fn generate_commit_message(
-        self,
+    /// The JSON body of a Messages request that forces the use of the given tool.
+    pub fn request_body(
         model: &str,
-        diff: &str,
-        context: Option<&str>,
-    ) -> ProviderResult<CommitMessage> {
-        trace!("Creating HTTP client with 120 seconds timeout");
-
-        // Build the JSON request body.
-        let user_description = context.unwrap_or("");
-        let content = format!(
-            "Git Diff:\n```diff\n{}\n```\n\nUser Description: {}",
-            diff, user_description
-        );
-
-        let request_body = json!({
+        system_prompt: &str,
+        content: &str,
+        tool: &ToolSpec,
+    ) -> serde_json::Value {
+        json!({
             "model": model,
             "max_tokens": 1024,
             "tools": [
                 {
-                    "name": "git_commit_message",
-                    "description": "Generate a commit message from a diff",
-                    "input_schema": {
-                        "type": "object",
-                        "properties": {
-                            "description": {
-                                "type": "string",
-                                "description": "A detailed description of the changes"
-                            },
-                            "summary": {
-                                "type": "string",
-                                "description": "A one-sentence description of the key change, starting with a capital letter."
-                            },
-                            "warning": {
-                                "type": "string",
-                                "description": "A string containing all detected potential sensitive information, or null if none found."
-                            }
-                        },
-                        "required": ["summary"]
-                    }
+                    "name": tool.name,
+                    "description": tool.description,
+                    "input_schema": tool.parameters
                 }
             ],
             "tool_choice": {
                 "type": "tool",
-                "name": "git_commit_message"
+                "name": tool.name
             },
+            "system": system_prompt,
             "messages": [
                 {
                     "role": "user",
-                    "content": format!("{}\n\n{}", prompt::get_system_prompt(), content)
+                    "content": content
                 }
             ]
-        });
+        })
+    }
+
+    /// Sends the provided prompt to the Anthropic Messages API and returns the tool input.
+    pub fn call_tool(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        content: &str,
+        tool: &ToolSpec,
+    ) -> ProviderResult<serde_json::Value> {
+        trace!("Creating HTTP client with 120 seconds timeout");
+
+        let request_body = Self::request_body(model, system_prompt, content, tool);
 
         // Send the POST request to the Anthropic Messages API.
         let response = self
             .client
-            .post(self.api_url)
-            .header("x-api-key", self.api_key)
+            .post(&self.api_url)
+            .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header(CONTENT_TYPE, "application/json")
             .json(&request_body)
@@ -141,8 +128,8 @@ impl AnthropicApi {
             crate::api::provider::ProviderError::InvalidFormat
         })?;
 
-        // Extract the commit message content from the response.
-        let tool_use = json_response
+        // Extract the tool input from the response.
+        json_response
             .content
             .into_iter()
             .find_map(|block| match block {
@@ -152,19 +139,6 @@ impl AnthropicApi {
             .ok_or_else(|| {
                 error!("No tool_use block found in the response");
                 crate::api::provider::ProviderError::InvalidFormat
-            })?;
-
-        let commit_message =
-            serde_json::from_value::<CommitMessageToolInput>(tool_use).map_err(|e| {
-                error!("Failed to parse tool input: {}", e);
-                crate::api::provider::ProviderError::InvalidFormat
-            })?;
-
-        // Return the CommitMessage
-        Ok(CommitMessage {
-            description: commit_message.description,
-            summary: commit_message.summary,
-            warning: commit_message.warning,
-        })
+            })
     }
 }
@@ -3,8 +3,10 @@ use reqwest::{blocking::Client, header::CONTENT_TYPE};
 use serde::Deserialize;
 use serde_json::json;
 
-use super::provider::{CommitMessage, ProviderResult};
-use crate::prompt;
+use super::provider::{ProviderResult, ToolSpec};
+
+/// The base URL of the public API.
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
 
 /// Structs for deserializing the OpenAI Chat Completions response.
 /// These structs are used to parse the JSON response from the OpenAI API.
@@ -40,13 +42,6 @@ struct ChatFunctionCall {
     arguments: String,
 }
 
-#[derive(Deserialize)]
-struct ChatFunctionCallResult {
-    description: Option<String>,
-    summary: String,
-    warning: Option<String>,
-}
-
 /// Struct for the OpenAI API client.
 pub struct OpenAiApi {
     api_key: String,
@@ -55,7 +50,8 @@ pub struct OpenAiApi {
 }
 
 impl OpenAiApi {
-    pub fn new(api_key: String) -> ProviderResult<Self> {
+    /// Create a client for the API at the given base URL, or the public API by default.
+    pub fn new(api_key: String, base_url: Option<&str>) -> ProviderResult<Self> {
         let client = Client::builder()
             .timeout(std::time::Duration::from_secs(120))
             .build()
@@ -69,36 +65,27 @@ impl OpenAiApi {
 
         Ok(OpenAiApi {
             api_key,
-            api_url: "https://api.openai.com/v1/chat/completions".to_string(),
+            api_url: format!(
+                "{}/chat/completions",
+                base_url.unwrap_or(DEFAULT_BASE_URL).trim_end_matches('/')
+            ),
             client,
         })
     }
 
-    /// Generates a commit message by sending the provided diff to the OpenAI ChatGPT API.
-    pub fn generate_commit_message(
-        self,
+    /// The JSON body of a Chat Completions request that forces a call of the given function.
+    pub fn request_body(
         model: &str,
-        diff: &str,
-        context: Option<&str>,
-    ) -> ProviderResult<CommitMessage> {
-        trace!("Creating HTTP client with 120 seconds timeout");
-
-        // Build the JSON request body.
-        let user_description = context.unwrap_or("");
-        let content = format!(
-            "Git Diff:
-            ```diff
-            {}
-            ```
-            User Description: {}",
-            diff, user_description
-        );
-        let request_body = json!({
+        system_prompt: &str,
+        content: &str,
+        tool: &ToolSpec,
+    ) -> serde_json::Value {
+        json!({
            "model": model,
            "messages": [
             {
                 "role": "system",
-                "content": prompt::get_system_prompt()
+                "content": system_prompt
             },
             {
                 "role": "user",
@@ -106,33 +93,32 @@ impl OpenAiApi {
             }],
             "functions": [
                 {
-                    "name": "git_commit_message",
-                    "description": "Generate a commit message from a diff",
-                    "parameters": {
-                        "type": "object",
-                        "properties": {
-                            "description": { "type": "string" },
-                            "summary": { "type": "string",
-                                "description": "A one-sentence description of the key change, starting with a capital letter."
-                            },
-                            "warning": {
-                                "type": "string",
-                                "description": "A string containing all detected potential sensitive information, or `null` if none found.",
-                            }
-                        },
-                        "required": ["summary"],
-                        "additionalProperties": false
-                    }
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters
                 }
             ],
-            "function_call": { "name": "git_commit_message" }
-        });
+            "function_call": { "name": tool.name }
+        })
+    }
+
+    /// Sends the provided prompt to the OpenAI ChatGPT API and returns the function call arguments.
+    pub fn call_tool(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        content: &str,
+        tool: &ToolSpec,
+    ) -> ProviderResult<serde_json::Value> {
+        trace!("Creating HTTP client with 120 seconds timeout");
+
+        let request_body = Self::request_body(model, system_prompt, content, tool);
 
         // Send the POST request to the OpenAI Chat Completions API.
         let response = self
             .client
-            .post(self.api_url)
-            .bearer_auth(self.api_key)
+            .post(&self.api_url)
+            .bearer_auth(&self.api_key)
             .header(CONTENT_TYPE, "application/json")
             .json(&request_body)
             .send()
@@ -177,7 +163,7 @@ impl OpenAiApi {
             crate::api::provider::ProviderError::InvalidFormat
         })?;
 
-        // Extract the commit message content from the response.
+        // Extract the function call arguments from the response.
         let function_call_result = &json_response
             .choices
             .first()
@@ -189,17 +175,9 @@ impl OpenAiApi {
             .function_call
             .arguments;
 
-        let commit_message = serde_json::from_str::<ChatFunctionCallResult>(function_call_result)
-            .map_err(|e| {
+        serde_json::from_str::<serde_json::Value>(function_call_result).map_err(|e| {
             error!("Failed to parse function call arguments: {}", e);
             crate::api::provider::ProviderError::InvalidFormat
-        })?;
-
-        // Return the CommitMessage
-        Ok(CommitMessage {
-            description: commit_message.description,
-            summary: commit_message.summary,
-            warning: commit_message.warning,
         })
     }
 }
@@ -1,16 +1,145 @@
 use std::fmt;
 
+use log::error;
 use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
 
 use super::anthropic::AnthropicApi;
 use super::openai::OpenAiApi;
+use crate::style::MessageStyle;
+use crate::warnings::{self, Warning};
 
 pub type ProviderResult<T> = std::result::Result<T, ProviderError>;
 
+#[derive(Deserialize)]
 pub struct CommitMessage {
     pub description: Option<String>,
     pub summary: String,
-    pub warning: Option<String>,
+    /// Possible sensitive information in the changes.
+    #[serde(default, deserialize_with = "warnings::deserialize")]
+    pub warnings: Vec<Warning>,
+    /// The `warning` string or list returned for templates written before warnings were
+    /// structured, merged into `warnings` after parsing.
+    #[serde(
+        default,
+        rename = "warning",
+        deserialize_with = "warnings::deserialize"
+    )]
+    legacy_warnings: Vec<Warning>,
+    /// The commit type, only requested by styles whose summary contains `{type}`.
+    #[serde(rename = "type", default)]
+    pub commit_type: Option<String>,
+    /// The affected component, only requested by styles whose summary contains `{scope}`.
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// The gitmoji shortcode, only requested by styles whose summary contains `{emoji}`.
+    #[serde(default)]
+    pub emoji: Option<String>,
+    /// A description of the breaking change, only requested by styles that mark breaking changes.
+    #[serde(default)]
+    pub breaking: Option<String>,
+}
+
+/// A function the model is forced to call, which defines the shape of the structured output.
+pub struct ToolSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// The JSON schema of the function arguments.
+    pub parameters: serde_json::Value,
+}
+
+impl ToolSpec {
+    /// The tool used to generate commit messages in the given style.
+    pub fn commit_message(style: &MessageStyle) -> Self {
+        let mut parameters = json!({
+            "type": "object",
+            "properties": {
+                "description": {
+                    "type": "string",
+                    "description": "A detailed description of the changes"
+                },
+                "summary": {
+                    "type": "string",
+                    "description": "A one-sentence description of the key change, starting with a capital letter."
+                },
+                "warnings": {
+                    "type": "array",
+                    "description": "Potential sensitive information in the added lines, or an empty array if none found.",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "file": {
+                                "type": ["string", "null"],
+                                "description": "The path of the file as shown in the diff."
+                            },
+                            "line": {
+                                "type": ["integer", "null"],
+                                "description": "The line number in the new version of the file, counted from the hunk header."
+                            },
+                            "kind": {
+                                "type": "string",
+                                "enum": ["secret", "personal_data", "other"],
+                                "description": "Whether the line contains a credential, personal data or other sensitive information."
+                            },
+                            "severity": {
+                                "type": "string",
+                                "enum": ["low", "medium", "high"],
+                                "description": "high for real credentials or personal data, low for likely placeholders or test values."
+                            },
+                            "message": {
+                                "type": "string",
+                                "description": "What was detected, without repeating the sensitive value."
+                            }
+                        },
+                        "required": ["file", "line", "kind", "severity", "message"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["summary"],
+            "additionalProperties": false
+        });
+
+        if style.prompt_instructions().is_some() {
+            parameters["properties"]["summary"]["description"] = json!(
+                "The subject of the commit in the imperative mood, without the other parts of the summary line."
+            );
+        }
+        if style.uses_type() {
+            parameters["properties"]["type"] = json!({
+                "type": "string",
+                "enum": style.types,
+                "description": "The type of the change."
+            });
+            parameters["required"] = json!(["summary", "type"]);
+        }
+        if style.uses_scope() {
+            parameters["properties"]["scope"] = json!({
+                "type": ["string", "null"],
+                "description": "A short lowercase noun naming the affected component, or null."
+            });
+        }
+        if style.uses_emoji() {
+            parameters["properties"]["emoji"] = json!({
+                "type": "string",
+                "enum": style.emojis.keys().collect::<Vec<_>>(),
+                "description": "The gitmoji shortcode describing the intent of the change."
+            });
+        }
+        if style.uses_breaking() {
+            parameters["properties"]["breaking"] = json!({
+                "type": ["string", "null"],
+                "description": "What users have to change because of this commit, or null if it is backwards compatible."
+            });
+        }
+
+        ToolSpec {
+            name: "git_commit_message",
+            description: "Generate a commit message from a diff",
+            parameters,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -35,6 +164,8 @@ impl fmt::Display for ProviderError {
     }
 }
 
+impl std::error::Error for ProviderError {}
+
 pub enum Provider {
     OpenAI(OpenAiApi),
     Anthropic(AnthropicApi),
@@ -51,23 +182,95 @@ impl Provider {
         }
     }
 
-    pub fn create_provider(provider: &str, api_key: &str) -> ProviderResult<Self> {
+    /// The provider of a model from a well-known family, to catch a model set for the wrong
+    /// provider.
+    pub fn for_model(model: &str) -> Option<&'static str> {
+        if model.starts_with("claude") {
+            Some("anthropic")
+        } else if ["gpt-", "chatgpt-", "o1", "o3", "o4"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+        {
+            Some("openai")
+        } else {
+            None
+        }
+    }
+
+    pub fn create_provider(
+        provider: &str,
+        api_key: &str,
+        base_url: Option<&str>,
+    ) -> ProviderResult<Self> {
         match provider {
-            "openai" => Ok(Provider::OpenAI(OpenAiApi::new(api_key.to_string())?)),
-            "anthropic" => Ok(Provider::Anthropic(AnthropicApi::new(api_key.to_string())?)),
+            "openai" => Ok(Provider::OpenAI(OpenAiApi::new(
+                api_key.to_string(),
+                base_url,
+            )?)),
+            "anthropic" => Ok(Provider::Anthropic(AnthropicApi::new(
+                api_key.to_string(),
+                base_url,
+            )?)),
             p => Err(ProviderError::UnsupportedProvider(p.to_string())),
         }
     }
 
     pub fn generate_commit_message(
-        self,
+        &self,
         model: &str,
-        prompt: &str,
-        context: Option<&str>,
+        style: &MessageStyle,
+        system_prompt: &str,
+        content: &str,
     ) -> ProviderResult<CommitMessage> {
+        let arguments = self.call_tool(
+            model,
+            system_prompt,
+            content,
+            &ToolSpec::commit_message(style),
+        )?;
+
+        let mut message = serde_json::from_value::<CommitMessage>(arguments).map_err(|e| {
+            error!("Failed to parse commit message: {}", e);
+            ProviderError::InvalidFormat
+        })?;
+        let mut legacy_warnings = std::mem::take(&mut message.legacy_warnings);
+        message.warnings.append(&mut legacy_warnings);
+
+        Ok(message)
+    }
+
+    /// The exact JSON body the provider would be sent for the prompt, without sending it.
+    pub fn request_body(
+        provider: &str,
+        model: &str,
+        system_prompt: &str,
+        content: &str,
+        tool: &ToolSpec,
+    ) -> ProviderResult<serde_json::Value> {
+        match provider {
+            "openai" => Ok(OpenAiApi::request_body(model, system_prompt, content, tool)),
+            "anthropic" => Ok(AnthropicApi::request_body(
+                model,
+                system_prompt,
+                content,
+                tool,
+            )),
+            p => Err(ProviderError::UnsupportedProvider(p.to_string())),
+        }
+    }
+
+    /// Send the prompt and force the model to answer by calling the given tool.
+    /// Returns the arguments of the tool call.
+    pub fn call_tool(
+        &self,
+        model: &str,
+        system_prompt: &str,
+        content: &str,
+        tool: &ToolSpec,
+    ) -> ProviderResult<serde_json::Value> {
         match self {
-            Provider::OpenAI(api) => api.generate_commit_message(model, prompt, context),
-            Provider::Anthropic(api) => api.generate_commit_message(model, prompt, context),
+            Provider::OpenAI(api) => api.call_tool(model, system_prompt, content, tool),
+            Provider::Anthropic(api) => api.call_tool(model, system_prompt, content, tool),
         }
     }
 }
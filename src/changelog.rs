@@ -0,0 +1,390 @@
+use std::error::Error;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use dialoguer::console::Term;
+use log::{error, info, trace};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::provider::{Provider, ProviderError, ToolSpec};
+use crate::config_manager::AppConfig;
+use crate::diff::{DiffSource, StagedChange};
+use crate::git;
+use crate::payload::{self, Outcome, Payload};
+use crate::prompt;
+use crate::template::Context;
+
+/// Changelog sections, in the order they are rendered.
+const SECTIONS: [&str; 7] = [
+    "Added",
+    "Changed",
+    "Deprecated",
+    "Removed",
+    "Fixed",
+    "Performance",
+    "Security",
+];
+
+/// The diff lines sent per commit, so long ranges stay within the prompt limits.
+const DIFF_EXCERPT_LINES: usize = 200;
+
+#[derive(Deserialize)]
+struct Changelog {
+    entries: Vec<ChangelogEntry>,
+}
+
+#[derive(Deserialize)]
+struct ChangelogEntry {
+    section: String,
+    text: String,
+    #[serde(default)]
+    breaking: bool,
+}
+
+/// The tool used to generate changelog entries.
+fn changelog_tool() -> ToolSpec {
+    ToolSpec {
+        name: "changelog",
+        description: "Generate changelog entries from a list of commits",
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "entries": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "section": { "type": "string", "enum": SECTIONS },
+                            "text": {
+                                "type": "string",
+                                "description": "A one-line, user-facing description of the change."
+                            },
+                            "breaking": {
+                                "type": "boolean",
+                                "description": "Whether users have to adapt to this change."
+                            }
+                        },
+                        "required": ["section", "text", "breaking"],
+                        "additionalProperties": false
+                    }
+                }
+            },
+            "required": ["entries"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+/// Generate release notes for the commits in a range, printing them or prepending them to a file.
+pub fn run(
+    config: &AppConfig,
+    range: &str,
+    release: Option<&str>,
+    prepend: Option<&Path>,
+) -> Result<(), Box<dyn Error>> {
+    let terminal = Term::stdout();
+
+    let commits = git::list_commits(range)?;
+    if commits.is_empty() {
+        terminal.write_line(&format!("No commits found in range {}", range))?;
+        return Ok(());
+    }
+
+    // Describe every commit by its message, the files it touched and the start of its diff.
+    // Merges are left out, as their changes are already described by the merged commits
+    let mut descriptions = Vec::new();
+    let mut diffs = Vec::new();
+    for commit in commits.iter().filter(|commit| !commit.is_merge) {
+        let diff = git::get_diff(
+            &DiffSource::Commit(commit.id.to_string()),
+            config.diff_settings(),
+        )?;
+        let files = diff
+            .files
+            .iter()
+            .map(|file| {
+                format!(
+                    "  {} {:?} (+{} -{})",
+                    file.path().display(),
+                    file.status,
+                    file.insertions(),
+                    file.deletions()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        descriptions.push(format!(
+            "Commit {}:\n{}\nFiles:\n{}\nDiff:\n```diff\n{}\n```",
+            commit.short_id(),
+            commit.message,
+            files,
+            excerpt(&diff.render(config.privacy), DIFF_EXCERPT_LINES)
+        ));
+        diffs.push(diff);
+    }
+    if descriptions.is_empty() {
+        terminal.write_line(&format!("Only merge commits found in range {}", range))?;
+        return Ok(());
+    }
+
+    // Look for secrets before anything leaves the machine
+    let Some((_, redactor)) = payload::screen(config, &diffs, config.privacy) else {
+        return Ok(());
+    };
+    let diff = StagedChange {
+        files: diffs.iter().flat_map(|diff| diff.files.clone()).collect(),
+        ignored: diffs.iter().flat_map(|diff| diff.ignored.clone()).collect(),
+    };
+
+    let template = config.template("changelog");
+    let system_prompt = template.system(&Context::default());
+    let content = prompt::build_changelog_content(
+        &template,
+        config.privacy,
+        config.get_user_desc(),
+        &descriptions,
+    );
+    trace!("Changelog input: \n{}", &content);
+
+    let mut payloads = [Payload {
+        label: None,
+        system_prompt,
+        content,
+        diff: &diff,
+    }];
+    let outcome = payload::prepare(
+        config,
+        &redactor,
+        &changelog_tool(),
+        &format!("Generate release notes for {} commits?", commits.len()),
+        &mut payloads,
+    )?;
+    if outcome != Outcome::Send {
+        return Ok(());
+    }
+    let [payload] = payloads;
+
+    let (provider, key) = config.get_provider_key();
+    let api = Provider::create_provider(provider, key, config.base_url.as_deref())?;
+
+    terminal.write_line("Generating release notes...")?;
+
+    let arguments = api.call_tool(
+        config.get_model(),
+        &payload.system_prompt,
+        &payload.content,
+        &changelog_tool(),
+    )?;
+    let changelog = serde_json::from_value::<Changelog>(arguments).map_err(|e| {
+        error!("Failed to parse changelog: {}", e);
+        ProviderError::InvalidFormat
+    })?;
+
+    // Released versions are dated by the last commit of the range, unreleased ones are not dated
+    let heading = match release {
+        Some(version) => {
+            let to = range
+                .split_once("..")
+                .map(|(_, to)| to.trim_start_matches('.'));
+            let to = to.filter(|to| !to.is_empty()).unwrap_or("HEAD");
+            format!("## [{}] - {}", version, format_date(git::commit_time(to)?))
+        }
+        None => "## [Unreleased]".to_string(),
+    };
+    let notes = render(&heading, &changelog.entries);
+
+    match prepend {
+        Some(path) => {
+            // Only a missing file starts a new changelog, any other error would overwrite it
+            let existing = match fs::read_to_string(path) {
+                Ok(existing) => existing,
+                Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e).into()),
+            };
+            fs::write(path, insert_release(&existing, &notes))?;
+            info!("Release notes written to {}", path.display());
+            terminal.write_line(&format!("Release notes added to {}", path.display()))?;
+        }
+        None => terminal.write_line(&format!("\n{}", notes))?,
+    }
+
+    Ok(())
+}
+
+/// The first lines of a patch, noting how many were left out.
+fn excerpt(patch: &str, max_lines: usize) -> String {
+    let total = patch.lines().count();
+    let mut excerpt = patch.lines().take(max_lines).collect::<Vec<_>>().join("\n");
+    if total > max_lines {
+        excerpt.push_str(&format!("\n... {} more lines", total - max_lines));
+    }
+
+    excerpt
+}
+
+/// Render the entries as a Keep a Changelog release section.
+fn render(heading: &str, entries: &[ChangelogEntry]) -> String {
+    let mut notes = format!("{}\n", heading);
+
+    for section in SECTIONS {
+        let items = entries
+            .iter()
+            .filter(|entry| entry.section.eq_ignore_ascii_case(section))
+            .map(|entry| match entry.breaking {
+                true => format!("- **BREAKING:** {}", entry.text),
+                false => format!("- {}", entry.text),
+            })
+            .collect::<Vec<_>>();
+
+        if !items.is_empty() {
+            notes.push_str(&format!("\n### {}\n{}\n", section, items.join("\n")));
+        }
+    }
+
+    notes
+}
+
+/// Insert the release notes above the latest release, below the changelog preamble.
+/// An empty `## [Unreleased]` section is replaced by the notes, unreleased notes are merged into
+/// a non-empty one, which stays on top when the notes are for a versioned release.
+fn insert_release(existing: &str, notes: &str) -> String {
+    if existing.trim().is_empty() {
+        return format!(
+            "# Changelog\n\nAll notable changes to this project will be documented in this file.\n\n{}",
+            notes
+        );
+    }
+
+    let Some(position) = existing.find("\n## [").map(|position| position + 1) else {
+        return format!("{}\n\n{}", existing.trim_end(), notes);
+    };
+    let (preamble, releases) = existing.split_at(position);
+
+    let Some(unreleased) = releases.strip_prefix("## [Unreleased]") else {
+        return format!("{}{}\n{}", preamble, notes, releases);
+    };
+
+    // The Unreleased section ends where the next release begins
+    let (unreleased_body, older) = match unreleased.find("\n## [") {
+        Some(end) => unreleased.split_at(end + 1),
+        None => (unreleased, ""),
+    };
+
+    if unreleased_body.trim().is_empty() {
+        format!("{}{}\n{}", preamble, notes, older)
+    } else if notes.starts_with("## [Unreleased]") {
+        let unreleased = format!("## [Unreleased]{}", unreleased_body);
+        format!(
+            "{}{}\n{}",
+            preamble,
+            merge_release(&unreleased, notes),
+            older
+        )
+    } else {
+        format!(
+            "{}## [Unreleased]{}\n\n{}\n{}",
+            preamble,
+            unreleased_body.trim_end(),
+            notes,
+            older
+        )
+    }
+}
+
+/// Merge the entries of the notes into an existing release section, section by section, leaving
+/// out entries it already has.
+fn merge_release(existing: &str, notes: &str) -> String {
+    let (heading, body) = existing.split_once('\n').unwrap_or((existing, ""));
+    let mut sections = parse_sections(body);
+
+    let (_, notes_body) = notes.split_once('\n').unwrap_or((notes, ""));
+    for (name, entries) in parse_sections(notes_body) {
+        match sections
+            .iter_mut()
+            .find(|(section, _)| section.eq_ignore_ascii_case(&name))
+        {
+            Some((_, existing)) => {
+                for entry in entries {
+                    if !existing.contains(&entry) {
+                        existing.push(entry);
+                    }
+                }
+            }
+            None => sections.push((name, entries)),
+        }
+    }
+
+    // Text above the first section stays first, known sections follow in their usual order
+    sections.sort_by_key(|(name, _)| match name.is_empty() {
+        true => 0,
+        false => SECTIONS
+            .iter()
+            .position(|section| section.eq_ignore_ascii_case(name))
+            .map_or(SECTIONS.len() + 1, |position| position + 1),
+    });
+
+    let mut merged = format!("{}\n", heading);
+    for (name, entries) in sections {
+        match name.is_empty() {
+            true => merged.push_str(&format!("\n{}\n", entries.join("\n"))),
+            false => merged.push_str(&format!("\n### {}\n{}\n", name, entries.join("\n"))),
+        }
+    }
+
+    merged
+}
+
+/// Split the body of a release section into its `###` sections and their entries. Indented
+/// lines belong to the entry above them, and text above the first section has an empty name.
+fn parse_sections(body: &str) -> Vec<(String, Vec<String>)> {
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+
+    for line in body.lines().filter(|line| !line.trim().is_empty()) {
+        if let Some(name) = line.strip_prefix("### ") {
+            sections.push((name.trim().to_string(), Vec::new()));
+            continue;
+        }
+        if sections.is_empty() {
+            sections.push((String::new(), Vec::new()));
+        }
+        let Some((_, entries)) = sections.last_mut() else {
+            continue;
+        };
+
+        match (line.starts_with(char::is_whitespace), entries.last_mut()) {
+            (true, Some(entry)) => {
+                entry.push('\n');
+                entry.push_str(line);
+            }
+            _ => entries.push(line.to_string()),
+        }
+    }
+
+    sections
+}
+
+/// Format a commit time as a `YYYY-MM-DD` date in the committer's time zone.
+fn format_date((seconds, offset_minutes): (i64, i32)) -> String {
+    let days = (seconds + i64::from(offset_minutes) * 60).div_euclid(86_400);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -32,3 +32,94 @@ pub fn prompt_for_confirmation(confirmation: &str) -> bool {
         .interact()
         .unwrap_or(false)
 }
+
+/// The options presented to the user before anything is sent to the provider.
+pub enum ProceedChoice {
+    Proceed,
+    ViewPayload,
+    Cancel,
+}
+
+/// Ask whether to send the prompt, offering to view it first.
+pub fn prompt_to_proceed(confirmation: &str) -> ProceedChoice {
+    let options = &["Yes", "No", "View payload"];
+
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt(confirmation)
+        .items(options)
+        .default(1)
+        .interact()
+        .unwrap_or(1);
+
+    match selection {
+        0 => ProceedChoice::Proceed,
+        2 => ProceedChoice::ViewPayload,
+        _ => ProceedChoice::Cancel,
+    }
+}
+
+/// The options presented to the user when reviewing a reworded commit.
+pub enum RewordChoice {
+    Accept,
+    Edit,
+    Keep,
+}
+
+/// Prompt the user to accept, edit or discard the new message of a reworded commit.
+pub fn prompt_reword_action() -> RewordChoice {
+    let options = &[
+        "Use new message",
+        "Edit new message",
+        "Keep original message",
+    ];
+
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .with_prompt("What would you like to do with this commit?")
+        .items(options)
+        .default(0)
+        .interact()
+        .unwrap_or(2);
+
+    match selection {
+        0 => RewordChoice::Accept,
+        1 => RewordChoice::Edit,
+        _ => RewordChoice::Keep,
+    }
+}
+
+/// Render two texts next to each other in columns that fit the given total width.
+pub fn side_by_side(left: (&str, &str), right: (&str, &str), width: usize) -> String {
+    let column_width = (width.saturating_sub(3) / 2).max(20);
+    let left_lines = wrap_lines(left.1, column_width);
+    let right_lines = wrap_lines(right.1, column_width);
+
+    let mut output = format!(
+        "{:<column_width$} | {}\n{} | {}\n",
+        left.0,
+        right.0,
+        "-".repeat(column_width),
+        "-".repeat(column_width)
+    );
+    for row in 0..left_lines.len().max(right_lines.len()) {
+        let left_line = left_lines.get(row).map(String::as_str).unwrap_or("");
+        let right_line = right_lines.get(row).map(String::as_str).unwrap_or("");
+        output.push_str(&format!("{:<column_width$} | {}\n", left_line, right_line));
+    }
+
+    output
+}
+
+/// Hard-wrap every line of the text so none is wider than the given number of characters.
+fn wrap_lines(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let chars = line.chars().collect::<Vec<_>>();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        for chunk in chars.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    lines
+}
@@ -1,26 +1,190 @@
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 use crate::api::provider::Provider;
+use crate::diff::{DiffSource, Privacy};
 
 /// Command-line arguments for ai-commit
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct CliConfig {
-    /// Specify the API key provider and key in the form <provider>=<key>.
-    #[arg(short = 'k', long = "api-key", value_name = "provider=key", value_parser = provider_key_parser)]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Specify the API key provider and key in the form <provider>=<key>, for this run only.
+    /// Keys are stored with `ai-commit config set api_keys.<provider>`.
+    #[arg(short = 'k', long = "api-key", value_name = "provider=key", value_parser = provider_key_parser, global = true)]
     pub api_key: Option<(String, String)>,
+    /// Use the named profile from the config file, which selects the provider, model and other
+    /// settings. Defaults to the `profile` setting.
+    #[arg(long = "profile", value_name = "name", global = true)]
+    pub profile: Option<String>,
     /// Specify the model to use for generating the commit message.
-    /// The value is cached in the config file, however,
-    /// it can be overridden by using the `--model` again.
-    #[arg(short = 'm', long)]
+    /// Defaults to the `model` setting.
+    #[arg(short = 'm', long, global = true)]
     pub model: Option<String>,
     /// Specify the additional context for the commit message, such as the issue number or a description.
-    #[arg(short = 'c', long = "context", value_name = "context")]
+    #[arg(short = 'c', long = "context", value_name = "context", global = true)]
     pub context: Option<String>,
-    /// Number of context lines to show in diff (default: 10)
-    #[arg(long = "context-lines", value_name = "lines", default_value = "10")]
-    pub context_lines: u32,
+    /// Specify the commit message style: `plain`, `conventional`, `gitmoji`, `kernel`, `angular` or a custom style.
+    /// Defaults to the `style` setting.
+    #[arg(long = "style", value_name = "style", global = true)]
+    pub style: Option<String>,
+    /// Add a `Signed-off-by` trailer with your git identity.
+    #[arg(short = 's', long = "signoff", global = true)]
+    pub signoff: bool,
+    /// Add a `Co-authored-by` trailer, either an alias from the roster in the config file or
+    /// `Name <email>`. Can be given multiple times.
+    #[arg(long = "co-author", value_name = "co-author", global = true)]
+    pub co_authors: Vec<String>,
+    /// How much of the code is sent: `stat-only` sends file paths, change counts and hunk
+    /// headers, `signatures` adds the changed function and type signatures, `full` sends the diff.
+    /// Defaults to the `privacy` setting of the repository, or `full`.
+    #[arg(long = "privacy", value_name = "mode", global = true)]
+    pub privacy: Option<Privacy>,
+    /// Write the request the provider would be sent to a file instead of sending it.
+    /// Defaults to `ai-commit-request.json` in the current directory.
+    #[arg(
+        long = "dry-run",
+        value_name = "file",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = crate::payload::DEFAULT_DRY_RUN_FILE,
+        global = true
+    )]
+    pub dry_run: Option<PathBuf>,
+    /// Number of context lines to show in diff. Defaults to the `context_lines` setting, or 10.
+    #[arg(long = "context-lines", value_name = "lines", global = true)]
+    pub context_lines: Option<u32>,
+    /// Include all changes to tracked files, not only the staged ones (like `git commit -a`).
+    /// Modified and deleted files are staged before committing.
+    #[arg(short = 'a', long = "all", conflicts_with_all = ["rev", "range", "amend"])]
+    pub all: bool,
+    /// Generate a message for an existing commit by diffing it against its parent.
+    /// The message is printed, nothing is committed.
+    #[arg(long = "rev", value_name = "commit", conflicts_with_all = ["range", "amend"])]
+    pub rev: Option<String>,
+    /// Generate a message describing a revision range, e.g. `main..feature` or `main...feature`.
+    /// The message is printed, nothing is committed.
+    #[arg(long = "range", value_name = "a..b", conflicts_with = "amend")]
+    pub range: Option<String>,
+    /// Rewrite the last commit with a new message covering its changes and anything staged since.
+    /// The existing message is passed to the model as prior context.
+    #[arg(long = "amend")]
+    pub amend: bool,
+    /// When amending, set the author and authored timestamp to the current user and time.
+    #[arg(long = "reset-author", requires = "amend")]
+    pub reset_author: bool,
+}
+
+/// Commands other than generating a message for the next commit
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate new messages for existing commits and rewrite them.
+    /// The original branch tip is saved under `refs/ai-commit/backup/` for rollback.
+    Reword {
+        /// The commits to reword, e.g. `main..HEAD`, or a single revision meaning `<rev>..HEAD`.
+        #[arg(value_name = "range")]
+        range: String,
+    },
+    /// Generate one consolidated message for squash-merging the current branch into `base`.
+    /// The message is printed unless `--commit` is given.
+    Squash {
+        /// The branch or commit the current branch will be merged into, e.g. `main`.
+        #[arg(value_name = "base")]
+        base: String,
+        /// Replace the branch's commits with a single commit, like `git reset --soft` followed
+        /// by `git commit`. The original tip is saved under `refs/ai-commit/backup/`.
+        #[arg(long = "commit")]
+        commit: bool,
+    },
+    /// Generate a pull request title and description for the current branch.
+    /// Follows `.github/pull_request_template.md` when the repository has one.
+    Pr {
+        /// The branch the pull request will be merged into.
+        #[arg(long = "base", value_name = "branch", default_value = "main")]
+        base: String,
+    },
+    /// Generate Keep a Changelog release notes for the commits in a range, e.g. `v1.0.0..v1.1.0`.
+    Changelog {
+        /// The commits to describe, e.g. `v1.0.0..v1.1.0` or `v1.0.0..` for everything since a tag.
+        #[arg(value_name = "from..to")]
+        range: String,
+        /// The version heading of the release. Defaults to `Unreleased`.
+        #[arg(long = "release", value_name = "version")]
+        release: Option<String>,
+        /// Insert the release notes into the given changelog file, above the latest release.
+        #[arg(long = "prepend", value_name = "file")]
+        prepend: Option<PathBuf>,
+    },
+    /// Inspect and change the settings.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+/// Operations on the settings
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Print the effective value of a setting, or of an entry like `scopes.web`.
+    Get {
+        #[arg(value_name = "key")]
+        key: String,
+    },
+    /// Show every effective setting and where it comes from.
+    ///
+    /// Settings come from the defaults, the global config, the repository's `.ai-commit.toml`,
+    /// an `AI_COMMIT_*` environment variable, the profile or the command line.
+    Explain,
+    /// Store a setting in the global config, e.g. `model gpt-4o` or `scopes.web frontend`.
+    ///
+    /// API keys are set with `api_keys.<credential>` and stored as `key_storage` says; without
+    /// a value, the key is asked for.
+    Set {
+        /// The setting, or `setting.entry` for an entry of a map or table.
+        #[arg(value_name = "key")]
+        key: String,
+        #[arg(value_name = "value")]
+        value: Option<String>,
+    },
+    /// Remove a setting, or an entry like `scopes.web`, from the global config.
+    ///
+    /// Removing `api_keys.<credential>` also removes the key from the encrypted key store.
+    Unset {
+        #[arg(value_name = "key")]
+        key: String,
+    },
+    /// List the settings stored in the global config.
+    List,
+    /// Open the global config in `$EDITOR`.
+    ///
+    /// The file is only saved when it is valid.
+    Edit,
+    /// Print the path of the global config.
+    Path,
+    /// Check the settings and report every problem.
+    ///
+    /// The global config, the repository's `.ai-commit.toml` and the environment are checked.
+    Validate,
+}
+
+impl CliConfig {
+    /// The changes to generate the commit message for, based on the selected flags.
+    pub fn diff_source(&self) -> DiffSource {
+        if let Some(rev) = &self.rev {
+            DiffSource::Commit(rev.clone())
+        } else if let Some(range) = &self.range {
+            DiffSource::Range(range.clone())
+        } else if self.amend {
+            DiffSource::Amend
+        } else if self.all {
+            DiffSource::WorkingTree
+        } else {
+            DiffSource::Staged
+        }
+    }
 }
 
 fn provider_key_parser(provider_key_string: &str) -> Result<(String, String)> {
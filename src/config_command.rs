@@ -0,0 +1,438 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use dialoguer::console::Term;
+use dialoguer::theme::ColorfulTheme;
+use log::{error, trace};
+use serde_json::{Map, Value};
+
+use crate::cli_config::{CliConfig, ConfigAction};
+use crate::config_layers::{self, Layers, Source};
+use crate::config_manager::{self, config_dir};
+use crate::cost_estimation;
+use crate::credentials::{self, KeyStorage};
+use crate::settings::{self, Kind};
+
+/// Shown instead of secret values.
+const MASK: &str = "********";
+
+/// Run a `config` operation.
+pub fn run(cli_config: &CliConfig, action: &ConfigAction) -> Result<(), Box<dyn Error>> {
+    match action {
+        ConfigAction::Get { key } => get(cli_config, key),
+        ConfigAction::Explain => explain(cli_config),
+        ConfigAction::Set { key, value } => set(key, value.as_deref()),
+        ConfigAction::Unset { key } => unset(key),
+        ConfigAction::List => list(),
+        ConfigAction::Edit => edit(),
+        ConfigAction::Path => {
+            println!("{}", global_path()?.display());
+            Ok(())
+        }
+        ConfigAction::Validate => validate(cli_config),
+    }
+}
+
+fn global_path() -> Result<PathBuf, &'static str> {
+    config_manager::get_config_path().ok_or("Could not determine config directory.")
+}
+
+/// Print the effective value of a setting, without quotes so it can be used in scripts.
+fn get(cli_config: &CliConfig, key: &str) -> Result<(), Box<dyn Error>> {
+    let (name, entry) = split(key);
+    let setting = settings::find(name).ok_or_else(|| format!("Unknown setting '{}'", name))?;
+    let (layers, _) = config_layers::load(cli_config)?;
+
+    let value = layers
+        .get(name)
+        .and_then(|value| match entry {
+            Some(entry) => lookup(value, entry),
+            None => Some(value),
+        })
+        .filter(|value| !value.is_null())
+        .ok_or_else(|| format!("{} is not set", key))?;
+    match (setting.secret, value) {
+        (true, _) => println!("{}", MASK),
+        (false, Value::String(text)) => println!("{}", text),
+        (false, value) => println!("{}", value),
+    }
+
+    Ok(())
+}
+
+/// Print every effective setting with the layer it comes from. Entries of maps and tables are
+/// listed one by one, as each can come from a different layer.
+fn explain(cli_config: &CliConfig) -> Result<(), Box<dyn Error>> {
+    let (layers, _) = config_layers::load(cli_config)?;
+
+    let mut rows = Vec::new();
+    for setting in settings::SETTINGS {
+        match layers.get(setting.key) {
+            Some(Value::Object(entries)) if !entries.is_empty() => {
+                for (entry, value) in entries {
+                    let name = format!("{}.{}", setting.key, entry);
+                    let source = layers.source(&name).to_string();
+                    rows.push((name, display(setting.secret, value), source));
+                }
+            }
+            Some(value) => rows.push((
+                setting.key.to_string(),
+                display(setting.secret, value),
+                layers.source(setting.key).to_string(),
+            )),
+            None => rows.push((
+                setting.key.to_string(),
+                "(not set)".to_string(),
+                layers.source(setting.key).to_string(),
+            )),
+        }
+    }
+    print_table(&rows);
+
+    Ok(())
+}
+
+/// Print the settings stored in the global config.
+fn list() -> Result<(), Box<dyn Error>> {
+    let path = global_path()?;
+    let global = config_manager::read_global(&path)?;
+
+    let mut rows = Vec::new();
+    for (key, value) in &global {
+        let secret = settings::find(key).is_some_and(|setting| setting.secret);
+        match value {
+            Value::Object(entries) if !entries.is_empty() => {
+                for (entry, value) in entries {
+                    rows.push((
+                        format!("{}.{}", key, entry),
+                        display(secret, value),
+                        String::new(),
+                    ));
+                }
+            }
+            value => rows.push((key.clone(), display(secret, value), String::new())),
+        }
+    }
+    match rows.is_empty() {
+        true => println!("No settings are stored in {}", path.display()),
+        false => print_table(&rows),
+    }
+
+    Ok(())
+}
+
+fn print_table(rows: &[(String, String, String)]) {
+    let key_width = rows.iter().map(|(key, _, _)| key.len()).max().unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .map(|(_, value, _)| value.len())
+        .max()
+        .unwrap_or(0)
+        .min(40);
+    for (key, value, source) in rows {
+        let line = format!("{:<key_width$}  {:<value_width$}  {}", key, value, source);
+        println!("{}", line.trim_end());
+    }
+}
+
+fn display(secret: bool, value: &Value) -> String {
+    match secret {
+        true => MASK.to_string(),
+        false => value.to_string(),
+    }
+}
+
+/// Store a setting in the global config. The whole file is checked before it is written, so a
+/// value of the wrong type is rejected instead of breaking every later run.
+fn set(key: &str, value: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let (name, entry) = split(key);
+    let setting = settings::find(name).ok_or_else(|| format!("Unknown setting '{}'", name))?;
+    if entry.is_some() && !matches!(setting.kind, Kind::Map | Kind::Table) {
+        return Err(format!("{} has no entries", name).into());
+    }
+
+    let text = match value {
+        Some(value) => value.to_string(),
+        None if setting.secret => read_secret(key)?,
+        None => return Err(format!("A value is required for {}", key).into()),
+    };
+    let value = match entry {
+        Some(_) => setting.parse_entry(&text),
+        None => setting
+            .parse(&text)
+            .map_err(|e| format!("Invalid {}: {}", key, e))?,
+    };
+
+    let path = global_path()?;
+    let mut global = config_manager::read_global(&path)?;
+    let before = global.clone();
+
+    // Keys are only written to the config file when the user chose plaintext storage
+    if setting.key == "api_keys" {
+        let storage = global
+            .get("key_storage")
+            .cloned()
+            .map(serde_json::from_value::<KeyStorage>)
+            .transpose()?
+            .unwrap_or_default();
+        if storage == KeyStorage::Encrypted {
+            let Some(credential) = entry else {
+                return Err("API keys are set one by one, e.g. api_keys.openai".into());
+            };
+            store_key(&mut global, credential, value.as_str().unwrap_or_default())?;
+            config_manager::write_global(&path, &global)?;
+            println!(
+                "Stored the {} API key in {}",
+                credential,
+                credentials::CREDENTIALS_FILE
+            );
+
+            return Ok(());
+        }
+    }
+
+    let mut target = &mut global;
+    let mut parts = key.split('.').peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            target.insert(part.to_string(), value);
+            break;
+        }
+        let next = target
+            .entry(part)
+            .or_insert_with(|| Value::Object(Map::new()));
+        if !next.is_object() {
+            *next = Value::Object(Map::new());
+        }
+        target = next.as_object_mut().expect("The entry was made an object");
+    }
+
+    check_change(&path, &before, &global)?;
+    config_manager::write_global(&path, &global)?;
+    match setting.secret {
+        true => println!("Set {} in {}", key, path.display()),
+        false => println!(
+            "Set {} to {} in {}",
+            key,
+            display(false, lookup_in(&global, key).unwrap_or(&Value::Null)),
+            path.display()
+        ),
+    }
+
+    Ok(())
+}
+
+/// Remove a setting from the global config, and API keys from the encrypted key store.
+fn unset(key: &str) -> Result<(), Box<dyn Error>> {
+    let path = global_path()?;
+    let mut global = config_manager::read_global(&path)?;
+    let before = global.clone();
+
+    let mut removed = remove(&mut global, key);
+    if let ("api_keys", Some(credential)) = split(key)
+        && let Some(dir) = config_dir()
+    {
+        removed |= credentials::remove(&dir, credential)?;
+    }
+    if !removed {
+        return Err(format!("{} is not set in {}", key, path.display()).into());
+    }
+
+    check_change(&path, &before, &global)?;
+    config_manager::write_global(&path, &global)?;
+    println!("Removed {}", key);
+
+    Ok(())
+}
+
+/// Open the global config in `$EDITOR`. The changes are made to a copy, which only replaces the
+/// config once it is valid, so a typo cannot break every later run.
+fn edit() -> Result<(), Box<dyn Error>> {
+    let path = global_path()?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => "{}\n".to_string(),
+    };
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir)?;
+    }
+    let draft = path.with_file_name("config.json.edit");
+    credentials::write_private(&draft, &content)?;
+
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "nano".to_string());
+    trace!("Using '{}' editor", editor);
+    let result = loop {
+        match Command::new(&editor).arg(&draft).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => break Err(format!("The editor exited with {}", status)),
+            Err(e) => break Err(format!("Failed to start editor '{}': {}", editor, e)),
+        }
+
+        let edited = fs::read_to_string(&draft)?;
+        let problems = match config_manager::parse_global(&path, &edited) {
+            Ok(global) => match problems(&path, &global) {
+                problems if problems.is_empty() => break Ok(edited),
+                problems => problems,
+            },
+            Err(e) => vec![e],
+        };
+        for problem in &problems {
+            error!("{}", problem);
+        }
+
+        let again = dialoguer::Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Edit again?")
+            .default(true)
+            .interact()
+            .unwrap_or(false);
+        if !again {
+            break Err("The config is invalid".to_string());
+        }
+    };
+    let _ = fs::remove_file(&draft);
+
+    match result {
+        Ok(edited) => {
+            credentials::write_private(&path, &edited)?;
+            println!("Saved {}", path.display());
+
+            Ok(())
+        }
+        Err(e) => Err(format!("{}, {} was not changed", e, path.display()).into()),
+    }
+}
+
+/// Check the settings of every layer and report all problems.
+fn validate(cli_config: &CliConfig) -> Result<(), Box<dyn Error>> {
+    let (layers, _) = config_layers::load(cli_config)?;
+    let config = layers.resolve()?;
+
+    if let Some(model) = &config.model
+        && cost_estimation::price_per_million(model).is_none()
+    {
+        eprintln!(
+            "Warning: the model '{}' is not known, costs are estimated with the default price",
+            model
+        );
+    }
+    let mut problems = config.validate();
+    problems.extend(config.check_model().err());
+    if problems.is_empty() {
+        println!("The configuration is valid");
+
+        return Ok(());
+    }
+
+    for problem in &problems {
+        error!("{}", problem);
+    }
+    match problems.len() {
+        1 => Err("Found a problem in the configuration".into()),
+        count => Err(format!("Found {} problems in the configuration", count).into()),
+    }
+}
+
+/// The problems of the global config on its own: values of the wrong type and what
+/// `AppConfig::validate` checks.
+fn problems(path: &Path, global: &Map<String, Value>) -> Vec<String> {
+    let mut layers = Layers::new();
+    layers.apply(global.clone(), &Source::Global(path.to_path_buf()));
+
+    match layers.resolve() {
+        Ok(config) => config.validate(),
+        Err(e) => vec![e],
+    }
+}
+
+/// Reject a change that adds problems to the global config. Existing problems are left to be
+/// fixed one at a time.
+fn check_change(
+    path: &Path,
+    before: &Map<String, Value>,
+    after: &Map<String, Value>,
+) -> Result<(), String> {
+    let existing = problems(path, before);
+    let added = problems(path, after)
+        .into_iter()
+        .filter(|problem| !existing.contains(problem))
+        .collect::<Vec<_>>();
+
+    match added.is_empty() {
+        true => Ok(()),
+        false => Err(added.join("\n")),
+    }
+}
+
+/// Split `scopes.web` into the setting and the entry.
+fn split(key: &str) -> (&str, Option<&str>) {
+    match key.split_once('.') {
+        Some((name, entry)) => (name, Some(entry)),
+        None => (key, None),
+    }
+}
+
+/// The value at a dotted path, e.g. `web` in `scopes`.
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |value, part| value.get(part))
+}
+
+/// The value at a dotted key, e.g. `scopes.web`.
+fn lookup_in<'a>(settings: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    let (name, entry) = split(key);
+    let value = settings.get(name)?;
+    match entry {
+        Some(entry) => lookup(value, entry),
+        None => Some(value),
+    }
+}
+
+/// Remove the value at a dotted key, and the maps left empty by it.
+fn remove(settings: &mut Map<String, Value>, key: &str) -> bool {
+    let (name, entry) = split(key);
+    let Some(entry) = entry else {
+        return settings.remove(name).is_some();
+    };
+    let Some(Value::Object(entries)) = settings.get_mut(name) else {
+        return false;
+    };
+
+    let removed = remove(entries, entry);
+    if entries.is_empty() {
+        settings.remove(name);
+    }
+    removed
+}
+
+/// Ask for a secret without echoing it, so it does not end up in the shell history.
+fn read_secret(key: &str) -> Result<String, Box<dyn Error>> {
+    if !Term::stderr().is_term() {
+        return Err(format!("A value is required for {}", key).into());
+    }
+
+    Ok(dialoguer::Password::with_theme(&ColorfulTheme::default())
+        .with_prompt(key)
+        .interact()?)
+}
+
+/// Store the key in the encrypted credential file. Plaintext keys in `config.json` are moved
+/// there as well, so they are not left behind.
+fn store_key(global: &mut Map<String, Value>, credential: &str, key: &str) -> Result<(), String> {
+    let dir = config_dir().ok_or("Could not determine config directory.")?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let plaintext = match global.remove("api_keys") {
+        Some(Value::Object(keys)) => keys,
+        _ => Map::new(),
+    };
+    let mut keys = plaintext
+        .iter()
+        .filter(|(name, _)| name.as_str() != credential)
+        .filter_map(|(name, key)| key.as_str().map(|key| (name.as_str(), key)))
+        .collect::<Vec<_>>();
+    keys.push((credential, key));
+
+    credentials::store(&dir, &keys)
+}
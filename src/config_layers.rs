@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use log::{debug, trace, warn};
+use serde_json::{Map, Value};
+
+use crate::cli_config::CliConfig;
+use crate::config_manager::{self, AppConfig, Profile};
+use crate::repo_config::{self, REPO_CONFIG_FILE, RepoConfig};
+use crate::settings;
+
+/// Where an effective setting came from. Later layers override earlier ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Global(PathBuf),
+    Repository(PathBuf),
+    Environment(String),
+    Profile(String),
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::Global(path) => write!(f, "global config ({})", path.display()),
+            Source::Repository(path) => write!(f, "repository config ({})", path.display()),
+            Source::Environment(name) => write!(f, "environment ({})", name),
+            Source::Profile(name) => write!(f, "profile {}", name),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// The settings merged from every layer, with the source of each value. Maps and tables are
+/// merged entry by entry, so a repository can add scopes without replacing the global ones.
+pub struct Layers {
+    values: Map<String, Value>,
+    /// Sources by key, and by `key.entry` for entries of maps and tables.
+    sources: BTreeMap<String, Source>,
+}
+
+impl Layers {
+    /// The built-in defaults.
+    pub fn new() -> Self {
+        let values = match serde_json::to_value(AppConfig::default()) {
+            Ok(Value::Object(values)) => values,
+            _ => Map::new(),
+        };
+        let sources = values
+            .keys()
+            .map(|key| (key.clone(), Source::Default))
+            .collect();
+
+        Layers { values, sources }
+    }
+
+    /// Apply a layer on top of the current values. Missing and `null` values leave the current
+    /// ones in place.
+    pub fn apply(&mut self, layer: Map<String, Value>, source: &Source) {
+        for (key, value) in layer {
+            match (self.values.get_mut(&key), value) {
+                (_, Value::Null) => {}
+                (Some(Value::Object(current)), Value::Object(entries)) => {
+                    for (entry, value) in entries {
+                        self.sources
+                            .insert(format!("{}.{}", key, entry), source.clone());
+                        current.insert(entry, value);
+                    }
+                }
+                (_, value) => {
+                    let prefix = format!("{}.", key);
+                    self.sources.retain(|name, _| !name.starts_with(&prefix));
+                    self.sources.insert(key.clone(), source.clone());
+                    self.values.insert(key, value);
+                }
+            }
+        }
+    }
+
+    /// The effective value of a setting.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key).filter(|value| !value.is_null())
+    }
+
+    /// Where the value of a setting, or of an entry like `scopes.web`, came from.
+    pub fn source(&self, key: &str) -> &Source {
+        self.sources
+            .get(key)
+            .or_else(|| {
+                key.split_once('.')
+                    .and_then(|(parent, _)| self.sources.get(parent))
+            })
+            .unwrap_or(&Source::Default)
+    }
+
+    /// The settings as the typed configuration. When a value has the wrong type, the error
+    /// names the setting and where it came from.
+    pub fn resolve(&self) -> Result<AppConfig, String> {
+        serde_json::from_value(Value::Object(self.values.clone())).map_err(|e| {
+            match self.invalid() {
+                Some(key) => format!("Invalid {} from {}: {}", key, self.source(&key), e),
+                None => format!("Invalid configuration: {}", e),
+            }
+        })
+    }
+
+    /// Find the setting, or the entry of a map or table, whose value has the wrong type, by
+    /// trying each on top of the defaults.
+    fn invalid(&self) -> Option<String> {
+        let defaults = Layers::new().values;
+        let is_valid = |key: &str, value: Value| {
+            let mut values = defaults.clone();
+            values.insert(key.to_string(), value);
+            serde_json::from_value::<AppConfig>(Value::Object(values)).is_ok()
+        };
+
+        let (key, value) = self
+            .values
+            .iter()
+            .find(|(key, value)| !is_valid(key, (*value).clone()))?;
+        let Value::Object(entries) = value else {
+            return Some(key.clone());
+        };
+        let entry = entries.iter().find(|(entry, value)| {
+            let mut single = match defaults.get(key.as_str()) {
+                Some(Value::Object(default)) => default.clone(),
+                _ => Map::new(),
+            };
+            single.insert(entry.to_string(), (*value).clone());
+            !is_valid(key, Value::Object(single))
+        });
+
+        match entry {
+            Some((entry, _)) => Some(format!("{}.{}", key, entry)),
+            None => Some(key.clone()),
+        }
+    }
+}
+
+/// Load the settings: the built-in defaults, then the global `config.json`, the repository's
+/// `.ai-commit.toml`, `AI_COMMIT_*` environment variables, the selected profile and finally the
+/// command line. The repository's own sections are returned with them.
+pub fn load(cli_config: &CliConfig) -> Result<(Layers, RepoConfig), String> {
+    let mut layers = Layers::new();
+
+    // A broken config file is an error, running with the defaults would silently drop settings
+    if let Some(path) = config_manager::get_config_path() {
+        let global = config_manager::read_global(&path)
+            .map_err(|e| format!("{}. Fix it with `ai-commit config edit`.", e))?;
+        layers.apply(global, &Source::Global(path));
+    }
+
+    let repo_config = repo_config::load()?;
+    if let Some(root) = &repo_config.root {
+        layers.apply(
+            repo_config.settings.clone(),
+            &Source::Repository(root.join(REPO_CONFIG_FILE)),
+        );
+    }
+
+    for setting in settings::SETTINGS {
+        if let Some(name) = setting.env_var()
+            && let Ok(text) = std::env::var(&name)
+        {
+            let value = setting
+                .parse(&text)
+                .map_err(|e| format!("Invalid {}: {}", name, e))?;
+            trace!("{} is set to {}", name, value);
+            layers.apply(single(setting.key, value), &Source::Environment(name));
+        }
+    }
+
+    // The profile sits between the environment and the command line, so options given with
+    // `--profile` still win over the profile
+    let mut command_line = Map::new();
+    if let Some((provider, _)) = &cli_config.api_key {
+        command_line.insert("api_provider".to_string(), provider.trim().into());
+    }
+    if let Some(model) = &cli_config.model {
+        command_line.insert("model".to_string(), model.as_str().into());
+    }
+    if let Some(style) = &cli_config.style {
+        command_line.insert("style".to_string(), style.as_str().into());
+    }
+    if let Some(context_lines) = cli_config.context_lines {
+        command_line.insert("context_lines".to_string(), context_lines.into());
+    }
+    if let Some(profile) = &cli_config.profile {
+        layers.apply(
+            single("profile", profile.as_str().into()),
+            &Source::CommandLine,
+        );
+    }
+    apply_profile(&mut layers)?;
+    layers.apply(command_line, &Source::CommandLine);
+
+    Ok((layers, repo_config))
+}
+
+/// Apply the settings of the selected profile. A profile selected by the repository is shared
+/// by everyone working on it and may not be defined on this machine, so it is ignored then.
+fn apply_profile(layers: &mut Layers) -> Result<(), String> {
+    let Some(name) = layers.get("profile").and_then(Value::as_str) else {
+        return Ok(());
+    };
+    let name = name.to_string();
+
+    let Some(profile) = layers
+        .get("profiles")
+        .and_then(|profiles| profiles.get(&name))
+    else {
+        if let Source::Repository(_) = layers.source("profile") {
+            warn!(
+                "The repository selects the profile '{}', which is not defined, using the global settings",
+                name
+            );
+            layers.values.insert("profile".to_string(), Value::Null);
+
+            return Ok(());
+        }
+
+        return Err(format!(
+            "Unknown profile '{}'. Profiles are defined in the profiles section of config.json.",
+            name
+        ));
+    };
+
+    let profile = serde_json::from_value::<Profile>(profile.clone())
+        .map_err(|e| format!("Invalid profile '{}': {}", name, e))?;
+    let Ok(Value::Object(mut values)) = serde_json::to_value(profile) else {
+        return Ok(());
+    };
+    if let Some(provider) = values.remove("provider") {
+        values.insert("api_provider".to_string(), provider);
+    }
+    debug!("Using profile {}", name);
+    layers.apply(values, &Source::Profile(name));
+
+    Ok(())
+}
+
+fn single(key: &str, value: Value) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    map
+}
@@ -1,172 +1,514 @@
 use directories::ProjectDirs;
-use log::{error, trace, warn};
+use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{self, ErrorKind};
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
+use serde_json::{Map, Value};
+
+use crate::api::provider::Provider;
 use crate::cli_config::CliConfig;
+use crate::config_layers;
+use crate::credentials::{self, KeyStorage};
+use crate::diff::{DiffSettings, Privacy};
+use crate::policy::{Allowlist, WarningPolicy};
+use crate::redact::RedactionConfig;
+use crate::repo_config::CustomPrompt;
+use crate::secrets::SecretScan;
+use crate::settings;
+use crate::style::MessageStyle;
+use crate::template::Template;
+use crate::ticket::TicketConfig;
+use crate::trailers;
 
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppConfig {
-    pub api_key: Option<String>,
     pub api_provider: Option<String>,
     pub model: Option<String>,
+    /// Additional context for the message, set with `--context`.
+    #[serde(skip)]
     pub user_desc: Option<String>,
     #[serde(default = "default_context_lines")]
     pub context_lines: u32,
+    /// Similarity percentage above which a delete/add pair is shown as a rename (0 disables).
+    #[serde(default = "default_rename_threshold")]
+    pub rename_threshold: u16,
+    /// Similarity percentage above which an added file is shown as a copy (0 disables).
+    #[serde(default = "default_copy_threshold")]
+    pub copy_threshold: u16,
+    /// The commit message style: a built-in preset or the name of a file in `styles/`.
+    pub style: Option<String>,
+    /// Maps path prefixes to commit scopes, e.g. `"services/billing": "billing"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scopes: BTreeMap<String, String>,
+    /// How many recent commits are read to learn the repository's message conventions (0 disables).
+    #[serde(default = "default_history_commits")]
+    pub history_commits: usize,
+    /// How ticket references are detected in branch names and added to messages.
+    #[serde(default)]
+    pub ticket: TicketConfig,
+    /// Trailers added to every message, e.g. `"Change-Type": "feature"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub trailers: BTreeMap<String, String>,
+    /// Maps co-author aliases to identities, e.g. `"alice": "Alice Smith <alice@example.com>"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub roster: BTreeMap<String, String>,
+    /// Maps template names (`commit`, `review`, `pr`, `changelog`) to override files.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub templates: BTreeMap<String, PathBuf>,
+    /// What happens when possible secrets are found in the changes: `confirm`, `block` or `off`.
+    #[serde(default)]
+    pub secret_scan: SecretScan,
+    /// Plaintext API keys by credential name, only written when `key_storage` is `plaintext`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub api_keys: BTreeMap<String, String>,
+    /// A command printing the API key, e.g. `pass show ai/{provider}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_command: Option<String>,
+    /// Where keys set with `ai-commit config set api_keys.<name>` are stored: `encrypted` or
+    /// `plaintext`.
+    #[serde(default)]
+    pub key_storage: KeyStorage,
+    /// The base URL of the provider API, for proxies and compatible servers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    /// Prompts estimated to cost more than this, in US dollars, are not sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+    /// Prompts with more tokens than this are not sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_prompt_tokens: Option<usize>,
+    /// Named combinations of provider, model and other settings, selected with `--profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// The selected profile.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// The name of the stored API key, defaults to the name of the provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    /// The API key of the provider, from `--api-key` or the key storage.
+    #[serde(skip)]
+    pub key: Option<String>,
+    /// Whether to add a `Signed-off-by` trailer, set with `--signoff`.
+    #[serde(skip)]
+    pub signoff: bool,
+    /// Co-author aliases or identities, set with `--co-author`.
+    #[serde(skip)]
+    pub co_authors: Vec<String>,
+    /// The prompt of the current repository, from `.ai-commit/prompt.md` or `.ai-commit.toml`.
+    #[serde(skip)]
+    pub prompt: Option<CustomPrompt>,
+    /// How secrets and personal data are redacted, from the `[redaction]` section of `.ai-commit.toml`.
+    #[serde(skip)]
+    pub redaction: RedactionConfig,
+    /// How much of the code is sent, from `--privacy` or `.ai-commit.toml`.
+    #[serde(skip)]
+    pub privacy: Privacy,
+    /// Where the request is written instead of being sent, set with `--dry-run`.
+    #[serde(skip)]
+    pub dry_run: Option<PathBuf>,
+    /// What happens when a new commit may contain sensitive information, from `.ai-commit.toml`.
+    #[serde(skip)]
+    pub warning_policy: WarningPolicy,
+    /// Files and lines whose sensitive information is accepted, from `.ai-commit-allowlist`.
+    #[serde(skip)]
+    pub allowlist: Allowlist,
+}
+
+/// A named set of settings that replace the global ones while the profile is selected.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// The name of the stored API key, so several keys of one provider can be kept apart.
+    /// Defaults to the name of the provider.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_prompt_tokens: Option<usize>,
 }
 
 fn default_context_lines() -> u32 {
     10
 }
 
-impl AppConfig {
-    pub fn api_key(&mut self, value: String) -> &mut Self {
-        self.api_key = Some(value);
-        self
-    }
+fn default_rename_threshold() -> u16 {
+    50
+}
 
-    pub fn api_provider(&mut self, value: String) -> &mut Self {
-        self.api_provider = Some(value);
-        self
-    }
+fn default_copy_threshold() -> u16 {
+    50
+}
 
-    pub fn model(&mut self, value: String) -> &mut Self {
-        self.model = Some(value);
-        self
-    }
+fn default_history_commits() -> usize {
+    50
+}
 
-    pub fn user_desc(&mut self, value: String) -> &mut Self {
-        self.user_desc = Some(value);
-        self
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            api_provider: None,
+            model: None,
+            user_desc: None,
+            context_lines: default_context_lines(),
+            rename_threshold: default_rename_threshold(),
+            copy_threshold: default_copy_threshold(),
+            style: None,
+            scopes: BTreeMap::new(),
+            history_commits: default_history_commits(),
+            ticket: TicketConfig::default(),
+            trailers: BTreeMap::new(),
+            roster: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            secret_scan: SecretScan::default(),
+            api_keys: BTreeMap::new(),
+            key_command: None,
+            key_storage: KeyStorage::default(),
+            base_url: None,
+            max_cost: None,
+            max_prompt_tokens: None,
+            profiles: BTreeMap::new(),
+            profile: None,
+            credential: None,
+            key: None,
+            signoff: false,
+            co_authors: Vec::new(),
+            prompt: None,
+            redaction: RedactionConfig::default(),
+            privacy: Privacy::default(),
+            dry_run: None,
+            warning_policy: WarningPolicy::default(),
+            allowlist: Allowlist::default(),
+        }
     }
+}
 
+impl AppConfig {
     pub fn get_model(&self) -> &str {
         self.model.as_deref().expect("Model field is missing")
     }
 
+    pub fn get_provider(&self) -> &str {
+        self.api_provider
+            .as_deref()
+            .expect("Provider field is missing")
+    }
+
     pub fn get_user_desc(&self) -> Option<&str> {
         self.user_desc.as_deref()
     }
 
+    /// The configured message style, falling back to `plain` if it cannot be loaded.
+    pub fn message_style(&self) -> MessageStyle {
+        let name = self.style.as_deref().unwrap_or("plain");
+
+        MessageStyle::load(name, config_dir().as_deref()).unwrap_or_else(|e| {
+            warn!("{}", e);
+
+            MessageStyle::load("plain", None).expect("The plain style is built in")
+        })
+    }
+
+    /// Load the prompt template with the given name, falling back to the built-in one if the
+    /// override cannot be loaded. The name and version are logged, so every generated message
+    /// can be traced back to the prompt that produced it.
+    pub fn template(&self, name: &str) -> Template {
+        let template = Template::load(name, &self.templates, config_dir().as_deref())
+            .unwrap_or_else(|e| {
+                warn!("{}", e);
+
+                Template::load(name, &BTreeMap::new(), None).expect("The template is built in")
+            });
+        info!(
+            "Using {} template version {}",
+            template.name, template.version
+        );
+
+        template
+    }
+
+    pub fn diff_settings(&self) -> DiffSettings {
+        DiffSettings {
+            context_lines: self.context_lines,
+            rename_threshold: self.rename_threshold.min(100),
+            copy_threshold: self.copy_threshold.min(100),
+        }
+    }
+
+    /// Check the settings beyond their types: names that must exist, patterns that must
+    /// compile and numbers that must be within range. Every problem is returned, not only the
+    /// first.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if let Some(provider) = &self.api_provider
+            && let Err(e) = Provider::validate(provider)
+        {
+            problems.push(e.to_string());
+        }
+        for (name, profile) in &self.profiles {
+            if let Some(provider) = &profile.provider
+                && let Err(e) = Provider::validate(provider)
+            {
+                problems.push(format!("Profile '{}': {}", name, e));
+            }
+        }
+        if let Some(base_url) = &self.base_url
+            && !base_url.starts_with("http://")
+            && !base_url.starts_with("https://")
+        {
+            problems.push(format!(
+                "base_url must be an http or https URL, got '{}'",
+                base_url
+            ));
+        }
+        if let Ok(Value::Object(values)) = serde_json::to_value(self) {
+            for setting in settings::SETTINGS {
+                if let Some(value) = values.get(setting.key)
+                    && let Err(e) = setting.check(value)
+                {
+                    problems.push(format!("{} {}", setting.key, e));
+                }
+            }
+        }
+
+        if let Some(Err(e)) = self
+            .style
+            .as_deref()
+            .map(|name| MessageStyle::load(name, config_dir().as_deref()))
+        {
+            problems.push(e.to_string());
+        }
+        if let Err(e) = self.ticket.validate() {
+            problems.push(e.to_string());
+        }
+        for key in self.trailers.keys() {
+            if !trailers::is_valid_key(key) {
+                problems.push(format!(
+                    "Invalid trailer key '{}', keys may only contain letters, digits and dashes",
+                    key
+                ));
+            }
+        }
+        for name in self.templates.keys() {
+            if let Err(e) = Template::load(name, &self.templates, config_dir().as_deref()) {
+                problems.push(e.to_string());
+            }
+        }
+
+        problems
+    }
+
+    /// Check that the model belongs to the provider. This is not part of `validate`, as the
+    /// model and provider are changed one at a time with `ai-commit config set`.
+    pub fn check_model(&self) -> Result<(), String> {
+        // Compatible servers behind a base URL serve models of any family
+        if let (Some(provider), Some(model)) = (&self.api_provider, &self.model)
+            && self.base_url.is_none()
+            && let Some(expected) = Provider::for_model(model)
+            && expected != provider
+        {
+            return Err(format!(
+                "The model '{}' is served by {}, but the provider is {}",
+                model, expected, provider
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn get_provider_key(&self) -> (&str, &str) {
-        self.api_key
+        self.key
             .as_deref()
             .and_then(|key| self.api_provider.as_deref().map(|provider| (provider, key)))
             .expect("API key or provider field is missing")
     }
 }
 
-fn get_config_path() -> Option<PathBuf> {
+/// The directory holding `config.json` and user-defined styles.
+pub fn config_dir() -> Option<PathBuf> {
     ProjectDirs::from("dev", "anton-kochev", "ai-commit")
-        .map(|proj_dirs| proj_dirs.config_dir().join("config.json"))
+        .map(|proj_dirs| proj_dirs.config_dir().to_path_buf())
 }
 
-pub fn load_config(cli_config: CliConfig) -> Result<AppConfig, &'static str> {
-    let mut config = match get_config_path() {
-        Some(config_path) => {
-            trace!("Loading configuration from {}", config_path.display());
-
-            match config_path.exists() {
-                true => match fs::read_to_string(&config_path) {
-                    Ok(content) => match serde_json::from_str(&content) {
-                        Ok(config) => {
-                            trace!("Configuration: {:?}", config);
-
-                            config
-                        }
-                        Err(e) => {
-                            warn!("Failed to parse config file. {}", e);
-
-                            AppConfig::default()
-                        }
-                    },
-                    Err(e) => {
-                        warn!("Failed to read config file: {}.", e);
-
-                        AppConfig::default()
-                    }
-                },
-                _ => {
-                    // Config file not found, return default config silently.
-                    // No info! log here anymore.
-                    trace!("Config file not found.");
-
-                    AppConfig::default()
-                }
-            }
+pub fn get_config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.json"))
+}
+
+/// Read the global config as it is stored, without defaults. A missing file has no settings.
+pub fn read_global(path: &Path) -> Result<Map<String, Value>, String> {
+    trace!("Loading configuration from {}", path.display());
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            trace!("Config file not found.");
+
+            return Ok(Map::new());
         }
-        None => {
-            warn!("Could not determine config directory.");
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
 
-            AppConfig::default()
+    parse_global(path, &content)
+}
+
+/// Parse the content of the global config, moving the key of earlier versions to `api_keys`.
+pub fn parse_global(path: &Path, content: &str) -> Result<Map<String, Value>, String> {
+    let mut settings = match serde_json::from_str(content) {
+        Ok(Value::Object(settings)) => settings,
+        Ok(_) => {
+            return Err(format!(
+                "Invalid {}: expected a JSON object",
+                path.display()
+            ));
         }
+        Err(e) => return Err(format!("Invalid {}: {}", path.display(), e)),
     };
 
-    // Update the config with CLI arguments
-    if let Some((provider, key)) = cli_config.api_key {
-        config.api_key(key).api_provider(provider);
-    }
-    if let Some(model) = cli_config.model {
-        config.model(model);
+    // The single key of earlier versions belongs to the provider it was used with
+    if let Some(Value::String(key)) = settings.remove("api_key")
+        && let Some(Value::String(provider)) = settings.get("api_provider").cloned()
+        && let Value::Object(keys) = settings
+            .entry("api_keys")
+            .or_insert_with(|| Value::Object(Map::new()))
+    {
+        keys.entry(provider).or_insert(Value::String(key));
     }
-    if let Some(context) = cli_config.context {
-        config.user_desc(context);
+    trace!("Configuration: {:?}", settings);
+
+    Ok(settings)
+}
+
+/// Write the global config, only readable by the current user as it may contain keys.
+pub fn write_global(path: &Path, settings: &Map<String, Value>) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("Failed to save {}: {}", path.display(), e);
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir).map_err(|e| error(&e))?;
     }
-    config.context_lines = cli_config.context_lines;
 
-    // Validate the mandatory fields
-    if config.model.is_none() {
-        error!("Model is not set. Please use -m/--model.");
+    let content = serde_json::to_string_pretty(settings).map_err(|e| error(&e))?;
+    credentials::write_private(path, &content).map_err(|e| error(&e))?;
+    trace!("Config file successfully updated");
 
-        return Err("Model is not set.");
+    Ok(())
+}
+
+/// Load the configuration from all layers, see `config_layers::load`. Nothing is written, the
+/// global config only changes with `ai-commit config set`.
+pub fn load_config(cli_config: CliConfig) -> Result<AppConfig, &'static str> {
+    let (layers, repo_config) = match config_layers::load(&cli_config) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("{}", e);
+
+            return Err("Invalid configuration.");
+        }
+    };
+    let mut config = match layers.resolve() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+
+            return Err("Invalid configuration.");
+        }
+    };
+    // Keys left in config.json from before they were encrypted
+    if config.key_storage == KeyStorage::Encrypted && !config.api_keys.is_empty() {
+        warn!(
+            "config.json stores API keys in plaintext. Store them again with `ai-commit config set api_keys.<name>` to encrypt them, or use an environment variable or key_command instead."
+        );
     }
-    if config.api_key.is_none() || config.api_provider.is_none() {
-        error!("API key is not set. Please use -k/--api-key.");
 
-        return Err("API key is not set.");
+    // Options that only apply to this run
+    if let Some((_, key)) = cli_config.api_key {
+        config.key = Some(key);
     }
+    config.user_desc = cli_config.context;
+    config.signoff = cli_config.signoff;
+    config.co_authors = cli_config.co_authors;
+    config.dry_run = cli_config.dry_run;
 
-    // Save the updated config
-    if let Err(e) = save_config(&config) {
-        error!("Failed to save config: {}", e);
+    // Validate the mandatory fields
+    if config.model.is_none() {
+        error!("Model is not set. Please use -m/--model or `ai-commit config set model <model>`.");
 
-        return Err("Failed to save config.");
+        return Err("Model is not set.");
     }
+    let mut problems = config.validate();
+    problems.extend(config.check_model().err());
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("{}", problem);
+        }
 
-    Ok(config)
-}
+        return Err("Invalid configuration.");
+    }
+    let Some(provider) = config.api_provider.clone() else {
+        error!(
+            "Provider is not set. Please use -k/--api-key or `ai-commit config set api_provider <provider>`."
+        );
 
-fn save_config(config: &AppConfig) -> io::Result<()> {
-    match get_config_path() {
-        Some(config_path) => {
-            // Ensure the parent directory exists
-            if let Some(parent_dir) = config_path.parent() {
-                fs::create_dir_all(parent_dir)?;
+        return Err("Provider is not set.");
+    };
+    // A dry run sends nothing, so it neither needs the key nor asks for a passphrase
+    if config.key.is_none() && config.dry_run.is_none() {
+        let credential = config
+            .credential
+            .clone()
+            .unwrap_or_else(|| provider.clone());
+        match credentials::resolve(
+            &provider,
+            &credential,
+            config.key_command.as_deref(),
+            config.api_keys.get(&credential).map(String::as_str),
+            config_dir().as_deref(),
+        ) {
+            Ok(Some((key, source))) => {
+                debug!("Using the {} API key from {}", credential, source);
+                config.key = Some(key);
             }
+            Ok(None) => {
+                error!(
+                    "API key is not set. Please use -k/--api-key, {}, key_command or `ai-commit config set api_keys.{}`.",
+                    credentials::env_var(&provider).unwrap_or("an environment variable"),
+                    credential
+                );
 
-            // Create a config copy
-            let config_to_save = AppConfig {
-                api_key: config.api_key.clone(),
-                api_provider: config.api_provider.clone(),
-                model: config.model.clone(),
-                user_desc: None, // Do not save user_desc
-                context_lines: config.context_lines,
-            };
-
-            // Serialize and save the config
-            let content = serde_json::to_string_pretty(&config_to_save)?;
+                return Err("API key is not set.");
+            }
+            Err(e) => {
+                error!("{}", e);
 
-            fs::write(&config_path, content)?;
+                return Err("Failed to read the API key.");
+            }
+        }
+    }
 
-            trace!("Config file successfully updated");
+    // The repository's own sections
+    if let Err(e) = repo_config.redaction.validate() {
+        error!("{}", e);
 
-            Ok(())
-        }
-        None => Err(io::Error::new(
-            ErrorKind::NotFound,
-            "Could not determine config directory.",
-        )),
+        return Err("Invalid repository config.");
     }
+    config.prompt = repo_config.prompt;
+    config.redaction = repo_config.redaction;
+    config.privacy = cli_config
+        .privacy
+        .or(repo_config.privacy)
+        .unwrap_or_default();
+    config.warning_policy = repo_config.policy.warnings;
+    config.allowlist = repo_config.allowlist;
+
+    Ok(config)
 }
@@ -1,4 +1,4 @@
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Ok, Result, bail};
 use log::warn;
 use tiktoken_rs::cl100k_base;
 
@@ -12,9 +12,23 @@ pub fn estimate_cost(model: &str, prompt: &str) -> Result<CostEstimate> {
     let tokenizer = cl100k_base().context("Failed to load tokenizer")?;
     let token_count = tokenizer.encode_with_special_tokens(prompt).len();
 
-    // Calculate cost based on model (INPUT token pricing per 1M tokens)
+    let price_per_million = price_per_million(model).unwrap_or_else(|| {
+        warn!(
+            "Unknown model '{}', using default pricing of $2.50 per 1M input tokens.",
+            model
+        );
+        2.50 // Default to gpt-4o pricing
+    });
+
+    let estimated_cost = (token_count as f64) * (price_per_million / 1_000_000.0);
+
+    Ok((token_count, estimated_cost))
+}
+
+/// The input price per 1M tokens of a known model, in US dollars.
+pub fn price_per_million(model: &str) -> Option<f64> {
     // Prices sourced from OpenAI and Anthropic pricing as of November 2025
-    let price_per_million = match model {
+    let price = match model {
         // ===== OpenAI Models =====
 
         // GPT-5 family (latest generation, released August 2025)
@@ -69,19 +83,10 @@ pub fn estimate_cost(model: &str, prompt: &str) -> Result<CostEstimate> {
         "claude-haiku-3.5" | "claude-3-5-haiku-20241022" | "claude-3-5-haiku-latest" => 0.80,
         "claude-haiku-3" | "claude-3-haiku-20240307" => 0.25,
 
-        // Default fallback for unknown models
-        _ => {
-            warn!(
-                "Unknown model '{}', using default pricing of $2.50 per 1M input tokens.",
-                model
-            );
-            2.50 // Default to gpt-4o pricing
-        }
+        _ => return None,
     };
 
-    let estimated_cost = (token_count as f64) * (price_per_million / 1_000_000.0);
-
-    Ok((token_count, estimated_cost))
+    Some(price)
 }
 
 pub fn format_cost_estimate(cost_estimate: &CostEstimate) -> String {
@@ -92,3 +97,33 @@ pub fn format_cost_estimate(cost_estimate: &CostEstimate) -> String {
         estimated_cost, token_count
     )
 }
+
+/// Check the estimate against the `max_prompt_tokens` and `max_cost` limits, if any.
+pub fn check_limits(
+    cost_estimate: &CostEstimate,
+    max_prompt_tokens: Option<usize>,
+    max_cost: Option<f64>,
+) -> Result<()> {
+    let (token_count, estimated_cost) = *cost_estimate;
+
+    if let Some(max) = max_prompt_tokens
+        && token_count > max
+    {
+        bail!(
+            "The prompt has {} tokens, more than the limit of {}",
+            token_count,
+            max
+        );
+    }
+    if let Some(max) = max_cost
+        && estimated_cost > max
+    {
+        bail!(
+            "The estimated cost of ${:.4} is above the limit of ${}",
+            estimated_cost,
+            max
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use dialoguer::console::Term;
+use log::{debug, trace};
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use serde::{Deserialize, Serialize};
+
+/// The encrypted credential file, stored next to `config.json`.
+pub const CREDENTIALS_FILE: &str = "credentials.enc";
+
+/// The environment variable holding the passphrase of the credential file.
+pub const PASSPHRASE_VAR: &str = "AI_COMMIT_PASSPHRASE";
+
+/// PBKDF2-HMAC-SHA256 rounds used to derive the encryption key from the passphrase.
+const PBKDF2_ITERATIONS: usize = 600_000;
+
+/// Where API keys given with `--api-key` are stored.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyStorage {
+    /// In the credential file, encrypted with a passphrase.
+    #[default]
+    Encrypted,
+    /// In `config.json`, as before keys were encrypted.
+    Plaintext,
+}
+
+/// Where an API key was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Environment(&'static str),
+    Command,
+    EncryptedFile,
+    ConfigFile,
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeySource::Environment(name) => write!(f, "the {} environment variable", name),
+            KeySource::Command => write!(f, "the key command"),
+            KeySource::EncryptedFile => write!(f, "the encrypted {}", CREDENTIALS_FILE),
+            KeySource::ConfigFile => write!(f, "config.json"),
+        }
+    }
+}
+
+/// The environment variable holding the API key of the provider.
+pub fn env_var(provider: &str) -> Option<&'static str> {
+    match provider {
+        "openai" => Some("OPENAI_API_KEY"),
+        "anthropic" => Some("ANTHROPIC_API_KEY"),
+        _ => None,
+    }
+}
+
+/// Find the API key stored as the credential, which is named after the provider unless a
+/// profile names it. The environment variable of the provider comes first, then the key
+/// command, the encrypted credential file and finally a plaintext key from `config.json`.
+/// The environment variable only holds the key of the provider's own credential, so it does
+/// not replace a key the profile names.
+pub fn resolve(
+    provider: &str,
+    credential: &str,
+    key_command: Option<&str>,
+    plaintext: Option<&str>,
+    config_dir: Option<&Path>,
+) -> Result<Option<(String, KeySource)>, String> {
+    if let Some(name) = env_var(provider).filter(|_| credential == provider)
+        && let Ok(key) = std::env::var(name)
+        && !key.trim().is_empty()
+    {
+        return Ok(Some((key.trim().to_string(), KeySource::Environment(name))));
+    }
+
+    if let Some(command) = key_command {
+        return run_key_command(command, provider, credential)
+            .map(|key| Some((key, KeySource::Command)));
+    }
+
+    if let Some(path) = config_dir.map(|dir| dir.join(CREDENTIALS_FILE))
+        && path.exists()
+    {
+        let store = CredentialStore::load(&path, &passphrase(false)?)?;
+        if let Some(key) = store.get(credential) {
+            return Ok(Some((key.to_string(), KeySource::EncryptedFile)));
+        }
+    }
+
+    Ok(plaintext.map(|key| (key.to_string(), KeySource::ConfigFile)))
+}
+
+/// Run the key command with `{provider}` and `{credential}` replaced, e.g.
+/// `pass show ai/{credential}`, and use the first line it prints as the key. The command can
+/// prompt on the terminal, only its output is captured.
+fn run_key_command(command: &str, provider: &str, credential: &str) -> Result<String, String> {
+    let command = command
+        .replace("{provider}", provider)
+        .replace("{credential}", credential);
+    trace!("Running key command '{}'", command);
+
+    let output = shell(&command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Failed to run key command '{}': {}", command, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Key command '{}' failed with {}",
+            command, output.status
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().next().map(str::trim) {
+        Some(key) if !key.is_empty() => Ok(key.to_string()),
+        _ => Err(format!("Key command '{}' printed no key", command)),
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+/// Store the keys by credential name in the encrypted credential file, creating it if needed.
+pub fn store(config_dir: &Path, keys: &[(&str, &str)]) -> Result<(), String> {
+    let path = config_dir.join(CREDENTIALS_FILE);
+    let (mut store, passphrase) = match path.exists() {
+        true => {
+            let passphrase = passphrase(false)?;
+            (CredentialStore::load(&path, &passphrase)?, passphrase)
+        }
+        false => (CredentialStore::default(), passphrase(true)?),
+    };
+
+    for (credential, key) in keys {
+        store.keys.insert(credential.to_string(), key.to_string());
+        debug!("Stored the {} API key in {}", credential, path.display());
+    }
+    store.save(&path, &passphrase)?;
+
+    Ok(())
+}
+
+/// Remove the key of the credential from the encrypted credential file. Returns whether it was
+/// stored there.
+pub fn remove(config_dir: &Path, credential: &str) -> Result<bool, String> {
+    let path = config_dir.join(CREDENTIALS_FILE);
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    let passphrase = passphrase(false)?;
+    let mut store = CredentialStore::load(&path, &passphrase)?;
+    if store.keys.remove(credential).is_none() {
+        return Ok(false);
+    }
+    debug!("Removed the {} API key from {}", credential, path.display());
+    store.save(&path, &passphrase)?;
+
+    Ok(true)
+}
+
+/// Read the passphrase of the credential file from the environment, or ask for it. A new
+/// passphrase is asked for twice.
+fn passphrase(new: bool) -> Result<String, String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    if !Term::stderr().is_term() {
+        return Err(format!(
+            "The API key store is encrypted. Set {} to unlock it without a terminal.",
+            PASSPHRASE_VAR
+        ));
+    }
+
+    let theme = dialoguer::theme::ColorfulTheme::default();
+    let mut prompt = dialoguer::Password::with_theme(&theme);
+    match new {
+        true => prompt
+            .with_prompt("New passphrase for the API key store")
+            .with_confirmation("Repeat the passphrase", "The passphrases do not match"),
+        false => prompt.with_prompt("Passphrase for the API key store"),
+    };
+
+    prompt
+        .interact()
+        .map_err(|e| format!("Failed to read the passphrase: {}", e))
+}
+
+/// The credential file as stored on disk. The keys are encrypted with AES-256-GCM, using a key
+/// derived from the passphrase with PBKDF2.
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+    tag: String,
+}
+
+/// API keys by credential name.
+#[derive(Default)]
+struct CredentialStore {
+    keys: BTreeMap<String, String>,
+}
+
+impl CredentialStore {
+    fn load(path: &Path, passphrase: &str) -> Result<Self, String> {
+        let invalid = |e: &dyn fmt::Display| format!("Invalid {}: {}", path.display(), e);
+        let content = fs::read_to_string(path).map_err(|e| invalid(&e))?;
+        let file = serde_json::from_str::<EncryptedFile>(&content).map_err(|e| invalid(&e))?;
+        if file.version != 1 {
+            return Err(invalid(&format!("unsupported version {}", file.version)));
+        }
+
+        let decode = |value: &str| STANDARD.decode(value).map_err(|e| invalid(&e));
+        let salt = decode(&file.salt)?;
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &derive_key(passphrase, &salt)?,
+            Some(&decode(&file.nonce)?),
+            &[],
+            &decode(&file.ciphertext)?,
+            &decode(&file.tag)?,
+        )
+        .map_err(|_| format!("Wrong passphrase for {}", path.display()))?;
+        let keys = serde_json::from_slice(&plaintext).map_err(|e| invalid(&e))?;
+
+        Ok(CredentialStore { keys })
+    }
+
+    fn save(&self, path: &Path, passphrase: &str) -> Result<(), String> {
+        let error = |e: &dyn fmt::Display| format!("Failed to save {}: {}", path.display(), e);
+        let mut salt = [0; 16];
+        let mut nonce = [0; 12];
+        rand_bytes(&mut salt).map_err(|e| error(&e))?;
+        rand_bytes(&mut nonce).map_err(|e| error(&e))?;
+
+        let plaintext = serde_json::to_vec(&self.keys).map_err(|e| error(&e))?;
+        let mut tag = [0; 16];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &derive_key(passphrase, &salt)?,
+            Some(&nonce),
+            &[],
+            &plaintext,
+            &mut tag,
+        )
+        .map_err(|e| error(&e))?;
+
+        let file = EncryptedFile {
+            version: 1,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+            tag: STANDARD.encode(tag),
+        };
+        let content = serde_json::to_string_pretty(&file).map_err(|e| error(&e))?;
+        write_private(path, &content).map_err(|e| error(&e))
+    }
+
+    fn get(&self, credential: &str) -> Option<&str> {
+        self.keys.get(credential).map(String::as_str)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let mut key = [0; 32];
+    pbkdf2_hmac(
+        passphrase.as_bytes(),
+        salt,
+        PBKDF2_ITERATIONS,
+        MessageDigest::sha256(),
+        &mut key,
+    )
+    .map_err(|e| format!("Failed to derive the encryption key: {}", e))?;
+
+    Ok(key)
+}
+
+/// Write a file only the current user can read and write, tightening the permissions of an
+/// existing file.
+pub fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(0o600);
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    options.open(path)?.write_all(content.as_bytes())
+}
@@ -0,0 +1,233 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A set of file changes produced by a diff, e.g. everything staged for the next commit.
+#[derive(Debug, Default, Clone)]
+pub struct StagedChange {
+    pub files: Vec<FileChange>,
+    /// Changed files left out because they match `.ai-commit-ignore`.
+    pub ignored: Vec<PathBuf>,
+}
+
+/// The kind of change made to a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    Renamed,
+    Copied,
+    TypeChange,
+}
+
+/// All changes made to a single file.
+#[derive(Debug, Clone)]
+pub struct FileChange {
+    pub old_path: Option<PathBuf>,
+    pub new_path: Option<PathBuf>,
+    pub status: ChangeStatus,
+    /// Similarity percentage for renamed and copied files.
+    pub similarity: Option<u16>,
+    pub is_binary: bool,
+    pub language: Option<&'static str>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// A contiguous block of changes within a file.
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    /// The function or section name that follows the `@@ -a,b +c,d @@` range, if any.
+    pub section: Option<String>,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Whether a diff line was kept, added or removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Addition,
+    Deletion,
+}
+
+/// A single line of a hunk.
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// The line content, without the trailing newline.
+    pub content: String,
+    /// The line number in the old file, for deletions and context lines.
+    pub old_lineno: Option<u32>,
+    /// The line number in the new file, for additions and context lines.
+    pub new_lineno: Option<u32>,
+}
+
+impl StagedChange {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl FileChange {
+    /// The path of the file after the change, falling back to the old path for deletions.
+    pub fn path(&self) -> &Path {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or(Path::new(""))
+    }
+
+    pub fn insertions(&self) -> usize {
+        self.count_lines(LineKind::Addition)
+    }
+
+    pub fn deletions(&self) -> usize {
+        self.count_lines(LineKind::Deletion)
+    }
+
+    /// The added lines with their line numbers in the new file.
+    pub fn added_lines(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| hunk.lines.iter())
+            .filter(|line| line.kind == LineKind::Addition)
+            .filter_map(|line| Some((line.new_lineno?, line.content.as_str())))
+    }
+
+    fn count_lines(&self, kind: LineKind) -> usize {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| hunk.lines.iter())
+            .filter(|line| line.kind == kind)
+            .count()
+    }
+
+    /// Write the `diff --git` header and the status-specific lines that follow it.
+    pub(super) fn write_header(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let old_path = self.old_path.as_deref().unwrap_or(self.path()).display();
+        let new_path = self.path().display();
+
+        writeln!(f, "diff --git a/{} b/{}", old_path, new_path)?;
+
+        match self.status {
+            // Moves are rendered compactly; the hunks below only show edits made on top of them.
+            ChangeStatus::Renamed | ChangeStatus::Copied => {
+                let kind = match self.status {
+                    ChangeStatus::Renamed => "renamed",
+                    _ => "copied",
+                };
+                writeln!(
+                    f,
+                    "{} {} -> {} ({}% similar)",
+                    kind,
+                    old_path,
+                    new_path,
+                    self.similarity.unwrap_or(100)
+                )?;
+                if self.is_binary {
+                    writeln!(f, "Binary files differ")?;
+                }
+                return Ok(());
+            }
+            ChangeStatus::Added => writeln!(f, "new file")?,
+            ChangeStatus::Deleted => writeln!(f, "deleted file")?,
+            ChangeStatus::TypeChange => writeln!(f, "type changed")?,
+            ChangeStatus::Modified => {}
+        }
+
+        if self.is_binary {
+            return writeln!(f, "Binary files differ");
+        }
+
+        if !self.hunks.is_empty() {
+            match self.status {
+                ChangeStatus::Added => writeln!(f, "--- /dev/null")?,
+                _ => writeln!(f, "--- a/{}", old_path)?,
+            }
+            match self.status {
+                ChangeStatus::Deleted => writeln!(f, "+++ /dev/null")?,
+                _ => writeln!(f, "+++ b/{}", new_path)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders the change set back into a unified patch.
+impl fmt::Display for StagedChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for file in &self.files {
+            write!(f, "{}", file)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FileChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_header(f)?;
+        for hunk in &self.hunks {
+            write!(f, "{}", hunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Hunk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "@@ -{},{} +{},{} @@",
+            self.old_start, self.old_lines, self.new_start, self.new_lines
+        )?;
+        match &self.section {
+            Some(section) => writeln!(f, " {}", section)?,
+            None => writeln!(f)?,
+        }
+        for line in &self.lines {
+            let origin = match line.kind {
+                LineKind::Context => ' ',
+                LineKind::Addition => '+',
+                LineKind::Deletion => '-',
+            };
+            writeln!(f, "{}{}", origin, line.content)?;
+        }
+        Ok(())
+    }
+}
+
+/// Guess the programming language of a file from its extension.
+pub fn detect_language(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let language = match extension.as_str() {
+        "rs" => "Rust",
+        "py" => "Python",
+        "js" | "mjs" | "cjs" | "jsx" => "JavaScript",
+        "ts" | "tsx" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "swift" => "Swift",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hpp" | "hh" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "scala" => "Scala",
+        "sh" | "bash" | "zsh" => "Shell",
+        "sql" => "SQL",
+        "html" | "htm" => "HTML",
+        "css" | "scss" | "sass" | "less" => "CSS",
+        "json" => "JSON",
+        "yml" | "yaml" => "YAML",
+        "toml" => "TOML",
+        "xml" => "XML",
+        "md" | "markdown" => "Markdown",
+        _ => return None,
+    };
+    Some(language)
+}
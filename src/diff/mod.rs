@@ -1,9 +1,66 @@
-use git2::{DiffFormat, Repository};
+use git2::{Delta, DiffDelta, DiffFindOptions, DiffFormat, Repository, RevparseMode, Tree};
 use log::{debug, warn};
 use std::path::Path;
 
-/// Get the staged diff with specified context lines
-pub fn get_staged_diff(context_lines: u32) -> Result<String, git2::Error> {
+mod change;
+mod privacy;
+
+pub use change::{
+    ChangeStatus, DiffLine, FileChange, Hunk, LineKind, StagedChange, detect_language,
+};
+pub use privacy::Privacy;
+
+/// Settings that control how the staged diff is produced.
+#[derive(Debug, Clone, Copy)]
+pub struct DiffSettings {
+    /// Number of unchanged lines shown around each change.
+    pub context_lines: u32,
+    /// Minimum similarity (0-100) for a delete/add pair to be reported as a rename.
+    /// A value of 0 disables rename detection.
+    pub rename_threshold: u16,
+    /// Minimum similarity (0-100) for an added file to be reported as a copy.
+    /// A value of 0 disables copy detection.
+    pub copy_threshold: u16,
+}
+
+/// The revisions a diff is computed between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffSource {
+    /// Staged changes, i.e. `HEAD` against the index.
+    Staged,
+    /// The changes of the `HEAD` commit plus anything staged since, i.e. `HEAD^` against the index.
+    Amend,
+    /// All changes to tracked files, i.e. `HEAD` against the working tree (like `git commit -a`).
+    WorkingTree,
+    /// A single existing commit against its first parent.
+    Commit(String),
+    /// A revision range such as `main..feature` or `main...feature`.
+    Range(String),
+}
+
+impl DiffSource {
+    /// Whether the changes can be committed, as opposed to describing existing history.
+    pub fn is_committable(&self) -> bool {
+        matches!(
+            self,
+            DiffSource::Staged | DiffSource::Amend | DiffSource::WorkingTree
+        )
+    }
+
+    /// A human readable description of the changes, used in status messages.
+    pub fn describe(&self) -> String {
+        match self {
+            DiffSource::Staged => "staged changes".to_string(),
+            DiffSource::Amend => "changes to amend".to_string(),
+            DiffSource::WorkingTree => "changes to tracked files".to_string(),
+            DiffSource::Commit(rev) => format!("changes in commit {}", rev),
+            DiffSource::Range(range) => format!("changes in range {}", range),
+        }
+    }
+}
+
+/// Get the diff for the given source with the specified settings
+pub fn get_diff(source: &DiffSource, settings: DiffSettings) -> Result<StagedChange, git2::Error> {
     // Discover and open the repository from the current directory.
     // This searches upward from the current directory to find the .git directory,
     // allowing the command to work from any subdirectory within the repository.
@@ -29,46 +86,223 @@ pub fn get_staged_diff(context_lines: u32) -> Result<String, git2::Error> {
         }
     };
 
-    // Get HEAD tree, if available.
-    let head_tree = match repo.head() {
-        Ok(reference) => Some(reference.peel_to_tree()?),
-        Err(_) => None,
-    };
-
-    // Get the index (staged changes).
-    let index = repo.index()?;
-
     // Create diff options with configurable context lines
     let mut diff_options = git2::DiffOptions::new();
-    diff_options.context_lines(context_lines);
+    diff_options.context_lines(settings.context_lines);
+
+    let mut diff = match source {
+        DiffSource::Staged => {
+            // Create a diff between the HEAD tree (if any) and the current index.
+            let head_tree = head_tree(&repo)?;
+            let index = repo.index()?;
+            repo.diff_tree_to_index(head_tree.as_ref(), Some(&index), Some(&mut diff_options))?
+        }
+        DiffSource::Amend => {
+            // Compare against the parent of HEAD so the amended commit is described as a whole.
+            let head = repo.head()?.peel_to_commit()?;
+            let parent_tree = match head.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            let index = repo.index()?;
+            repo.diff_tree_to_index(parent_tree.as_ref(), Some(&index), Some(&mut diff_options))?
+        }
+        DiffSource::WorkingTree => {
+            // Tracked files only; untracked files are not part of `git commit -a` either.
+            let head_tree = head_tree(&repo)?;
+            repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_options))?
+        }
+        DiffSource::Commit(rev) => {
+            let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+            // The root commit has no parent and is diffed against an empty tree.
+            let parent_tree = match commit.parent(0) {
+                Ok(parent) => Some(parent.tree()?),
+                Err(_) => None,
+            };
+            repo.diff_tree_to_tree(
+                parent_tree.as_ref(),
+                Some(&commit.tree()?),
+                Some(&mut diff_options),
+            )?
+        }
+        DiffSource::Range(range) => {
+            let (from, to) = resolve_range(&repo, range)?;
+            repo.diff_tree_to_tree(Some(&from), Some(&to), Some(&mut diff_options))?
+        }
+    };
 
-    // Create a diff between the HEAD tree (if any) and the current index.
-    let diff = repo.diff_tree_to_index(head_tree.as_ref(), Some(&index), Some(&mut diff_options))?;
+    // Pair up deleted and added files so moves are not reported as full rewrites.
+    if settings.rename_threshold > 0 || settings.copy_threshold > 0 {
+        let mut find_options = DiffFindOptions::new();
+        find_options
+            .renames(settings.rename_threshold > 0)
+            .rename_threshold(settings.rename_threshold)
+            .copies(settings.copy_threshold > 0)
+            .copy_threshold(settings.copy_threshold);
+        diff.find_similar(Some(&mut find_options))?;
+    }
 
-    // Generate the diff output
-    let mut diff_str = String::new();
+    let mut change = StagedChange::default();
 
-    // Print the diff in Patch format, filtering out ignored files.
-    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+    // Walk the diff in Patch format, filtering out ignored files.
+    diff.print(DiffFormat::Patch, |delta, hunk, line| {
         // Get the file path from the diff delta (prefer new file path, fallback to old).
         let file_path = delta.new_file().path().or(delta.old_file().path());
-        if let Some(path) = file_path {
-            if ignore_set.is_match(path) {
-                // Skip lines for files that match ignore patterns.
-                return true;
+        if let Some(path) = file_path
+            && ignore_set.is_match(path)
+        {
+            // Skip lines for files that match ignore patterns, but remember the file.
+            if line.origin() == 'F' {
+                change.ignored.push(path.to_path_buf());
             }
+            return true;
         }
-        if let Ok(content) = std::str::from_utf8(line.content()) {
-            // Add the line origin character (+, -, or space) before the content
-            let origin = line.origin();
-            match origin {
-                '+' | '-' | ' ' => diff_str.push(origin),
-                _ => {}
+
+        match line.origin() {
+            // The file header is emitted once per file and starts a new entry.
+            'F' => {
+                let header = String::from_utf8_lossy(line.content());
+                change.files.push(file_change_from_delta(&delta, &header));
             }
-            diff_str.push_str(content);
+            'B' => {
+                if let Some(file) = change.files.last_mut() {
+                    file.is_binary = true;
+                }
+            }
+            'H' => {
+                if let (Some(file), Some(hunk)) = (change.files.last_mut(), hunk)
+                    && !file.is_binary
+                {
+                    file.hunks.push(Hunk {
+                        section: hunk_section(hunk.header()),
+                        old_start: hunk.old_start(),
+                        old_lines: hunk.old_lines(),
+                        new_start: hunk.new_start(),
+                        new_lines: hunk.new_lines(),
+                        lines: Vec::new(),
+                    });
+                }
+            }
+            origin @ ('+' | '-' | ' ') => {
+                let kind = match origin {
+                    '+' => LineKind::Addition,
+                    '-' => LineKind::Deletion,
+                    _ => LineKind::Context,
+                };
+                let Some(file) = change.files.last_mut() else {
+                    return true;
+                };
+                if file.is_binary {
+                    return true;
+                }
+                match std::str::from_utf8(line.content()) {
+                    Ok(content) => {
+                        if let Some(hunk) = file.hunks.last_mut() {
+                            hunk.lines.push(DiffLine {
+                                kind,
+                                content: content.trim_end_matches(['\n', '\r']).to_string(),
+                                old_lineno: line.old_lineno(),
+                                new_lineno: line.new_lineno(),
+                            });
+                        }
+                    }
+                    // Content libgit2 did not recognise as binary but which is not valid text.
+                    Err(_) => {
+                        file.is_binary = true;
+                        file.hunks.clear();
+                    }
+                }
+            }
+            // End-of-file newline markers carry no content worth keeping.
+            _ => {}
         }
         true
     })?;
 
-    Ok(diff_str)
+    Ok(change)
+}
+
+/// Get the tree of the current HEAD commit, or `None` in a repository without commits.
+fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, git2::Error> {
+    match repo.head() {
+        Ok(reference) => Ok(Some(reference.peel_to_tree()?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Resolve a revision range into the two trees to compare.
+/// `a..b` compares `a` with `b`, `a...b` compares the merge base of both with `b`,
+/// and a single revision is compared with `HEAD`.
+fn resolve_range<'r>(
+    repo: &'r Repository,
+    range: &str,
+) -> Result<(Tree<'r>, Tree<'r>), git2::Error> {
+    let spec = repo.revparse(range)?;
+
+    let from = spec
+        .from()
+        .ok_or_else(|| git2::Error::from_str("Range is missing a start revision"))?
+        .peel_to_commit()?;
+    let to = match spec.to() {
+        Some(to) => to.peel_to_commit()?,
+        None => repo.head()?.peel_to_commit()?,
+    };
+
+    let from = if spec.mode().contains(RevparseMode::MERGE_BASE) {
+        let base = repo.merge_base(from.id(), to.id())?;
+        repo.find_commit(base)?
+    } else {
+        from
+    };
+
+    Ok((from.tree()?, to.tree()?))
+}
+
+/// Create an empty file entry from a diff delta and its raw patch header.
+fn file_change_from_delta(delta: &DiffDelta, header: &str) -> FileChange {
+    let status = match delta.status() {
+        Delta::Added | Delta::Untracked => ChangeStatus::Added,
+        Delta::Deleted => ChangeStatus::Deleted,
+        Delta::Renamed => ChangeStatus::Renamed,
+        Delta::Copied => ChangeStatus::Copied,
+        Delta::Typechange => ChangeStatus::TypeChange,
+        _ => ChangeStatus::Modified,
+    };
+
+    // libgit2 does not expose the similarity score, but it is part of the header.
+    let similarity = header
+        .lines()
+        .find_map(|line| line.strip_prefix("similarity index "))
+        .and_then(|value| value.trim_end_matches('%').parse().ok());
+
+    let old_path = delta.old_file().path().map(|path| path.to_path_buf());
+    let new_path = delta.new_file().path().map(|path| path.to_path_buf());
+    let language = new_path
+        .as_deref()
+        .or(old_path.as_deref())
+        .and_then(detect_language);
+
+    FileChange {
+        old_path: match status {
+            ChangeStatus::Added => None,
+            _ => old_path,
+        },
+        new_path: match status {
+            ChangeStatus::Deleted => None,
+            _ => new_path,
+        },
+        status,
+        similarity,
+        is_binary: delta.flags().is_binary(),
+        language,
+        hunks: Vec::new(),
+    }
+}
+
+/// Extract the function or section name from a raw `@@ -a,b +c,d @@ section` hunk header.
+fn hunk_section(header: &[u8]) -> Option<String> {
+    let header = String::from_utf8_lossy(header);
+    let section = header.splitn(3, "@@").nth(2)?.trim();
+
+    (!section.is_empty()).then(|| section.to_string())
 }
@@ -0,0 +1,154 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::change::{FileChange, LineKind, StagedChange};
+
+/// How much of the changed code is sent to the provider.
+#[derive(Deserialize, ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Privacy {
+    /// Only file paths, change counts and hunk headers with their function names.
+    StatOnly,
+    /// Like `stat-only`, plus the changed function and type signatures and their line numbers,
+    /// without their bodies.
+    Signatures,
+    /// The complete diff.
+    #[default]
+    Full,
+}
+
+impl Privacy {
+    /// An explanation for the model of what was left out, or `None` for the full diff.
+    pub fn note(&self) -> Option<&'static str> {
+        match self {
+            Privacy::StatOnly => Some(
+                "The code was withheld. The diff only contains file headers, change counts and hunk headers with the enclosing function names. Describe the change from this metadata and do not guess at details.",
+            ),
+            Privacy::Signatures => Some(
+                "The code was withheld. The diff only contains file headers, change counts, hunk headers and the added or removed function and type signatures with their line numbers, without their bodies. Describe the change from this metadata and do not guess at details.",
+            ),
+            Privacy::Full => None,
+        }
+    }
+}
+
+impl StagedChange {
+    /// Render the changes as a patch that contains only what the privacy mode allows.
+    pub fn render(&self, privacy: Privacy) -> String {
+        match privacy {
+            Privacy::Full => self.to_string(),
+            _ => Metadata {
+                change: self,
+                signatures: privacy == Privacy::Signatures,
+            }
+            .to_string(),
+        }
+    }
+}
+
+/// The file and hunk headers of a change, optionally with the changed signatures. Hunk
+/// sections are only kept when they are a signature in a known language.
+struct Metadata<'a> {
+    change: &'a StagedChange,
+    signatures: bool,
+}
+
+impl fmt::Display for Metadata<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for file in &self.change.files {
+            file.write_header(f)?;
+            if file.is_binary {
+                continue;
+            }
+            writeln!(f, "changes: +{} -{}", file.insertions(), file.deletions())?;
+
+            let pattern = signature_pattern(file);
+            for hunk in &file.hunks {
+                write!(
+                    f,
+                    "@@ -{},{} +{},{} @@",
+                    hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+                )?;
+                // Git takes any line starting with a letter for the section, which may be code
+                match (&hunk.section, &pattern) {
+                    (Some(section), Some(pattern)) if is_signature(pattern, section) => {
+                        writeln!(f, " {}", strip_body(section))?
+                    }
+                    _ => writeln!(f)?,
+                }
+
+                let Some(pattern) = pattern.as_ref().filter(|_| self.signatures) else {
+                    continue;
+                };
+                // Removed signatures are numbered in the old file, added ones in the new file
+                for line in &hunk.lines {
+                    let (origin, lineno) = match line.kind {
+                        LineKind::Addition => ('+', line.new_lineno),
+                        LineKind::Deletion => ('-', line.old_lineno),
+                        LineKind::Context => continue,
+                    };
+                    if is_signature(pattern, &line.content) {
+                        match lineno {
+                            Some(lineno) => {
+                                writeln!(f, "{}{}: {}", origin, lineno, strip_body(&line.content))?
+                            }
+                            None => writeln!(f, "{}{}", origin, strip_body(&line.content))?,
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The pattern matching lines that declare a function or type in the language of the file.
+fn signature_pattern(file: &FileChange) -> Option<Regex> {
+    let pattern = match file.language? {
+        "Rust" => {
+            r"^\s*(?:pub(?:\([^)]*\))?\s+)?(?:(?:async|const|unsafe|extern(?:\s+\x22[^\x22]*\x22)?)\s+)*(?:fn|struct|enum|trait|impl|type|mod|union)\b"
+        }
+        "Python" => r"^\s*(?:async\s+)?(?:def|class)\s",
+        "JavaScript" | "TypeScript" => {
+            r"^\s*(?:export\s+)?(?:default\s+)?(?:declare\s+)?(?:abstract\s+)?(?:async\s+)?(?:function\*?|class|interface|type|enum)\s"
+        }
+        "Go" => r"^\s*(?:func|type)\s",
+        "Java" | "Kotlin" | "C#" | "Scala" | "Swift" => {
+            r"^\s*(?:(?:public|private|protected|internal|static|final|abstract|override|open|sealed|data|async|virtual|partial)\s+)*(?:class|interface|enum|record|struct|object|trait|fun|func|def|[\w<>\[\],.?]+\s+\w+\s*\()"
+        }
+        "C" | "C++" => {
+            r"^(?:\s*(?:class|struct|enum|union|typedef|namespace)\s|[A-Za-z_][\w\s\*&:<>,]*[\s\*&]\**[A-Za-z_][\w:~]*\s*\()"
+        }
+        "Ruby" => r"^\s*(?:def|class|module)\s",
+        "PHP" => {
+            r"^\s*(?:(?:public|private|protected|static|abstract|final)\s+)*(?:function|class|interface|trait|enum)\s"
+        }
+        "Shell" => r"^\s*(?:function\s+\w+|\w+\s*\(\s*\))",
+        _ => return None,
+    };
+
+    Some(Regex::new(pattern).expect("Invalid signature pattern"))
+}
+
+/// Whether the line declares a function or type. Statements such as `return foo(bar)` are
+/// excluded, as the looser patterns of C-like languages would take them for declarations.
+fn is_signature(pattern: &Regex, line: &str) -> bool {
+    const STATEMENTS: [&str; 8] = [
+        "return ", "throw ", "new ", "else ", "case ", "yield ", "await ", "delete ",
+    ];
+    let trimmed = line.trim_start();
+
+    pattern.is_match(line)
+        && !STATEMENTS
+            .iter()
+            .any(|keyword| trimmed.starts_with(keyword))
+}
+
+/// Cut a declaration at the start of its body, e.g. `fn parse(input: &str) -> Ast`.
+fn strip_body(line: &str) -> &str {
+    let end = line.find('{').unwrap_or(line.len());
+    line[..end].trim_end()
+}
@@ -1,7 +1,108 @@
-use git2::Repository;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Re-export get_staged_diff from the diff module
-pub use crate::diff::get_staged_diff;
+use git2::{Oid, Repository, ResetType, Sort};
+
+/// Re-export get_diff from the diff module
+pub use crate::diff::get_diff;
+
+/// Stage every modification and deletion of tracked files, like `git commit -a` does.
+pub fn stage_tracked_changes() -> Result<(), git2::Error> {
+    let repo = Repository::discover(".")?;
+
+    let mut index = repo.index()?;
+    index.update_all(["*"], None)?;
+    index.write()?;
+
+    Ok(())
+}
+
+/// Get the root directory of the working tree of the current repository
+pub fn repo_root() -> Result<PathBuf, git2::Error> {
+    let repo = Repository::discover(".")?;
+
+    repo.workdir()
+        .map(Path::to_path_buf)
+        .ok_or_else(|| git2::Error::from_str("Repository has no working directory"))
+}
+
+/// Get the short name of the currently checked out branch, if HEAD points at one
+pub fn current_branch() -> Result<Option<String>, git2::Error> {
+    let repo = Repository::discover(".")?;
+    let head = match repo.head() {
+        Ok(head) => head,
+        // An unborn branch has no commits yet, but HEAD still names it
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => {
+            let head = repo.find_reference("HEAD")?;
+            return Ok(head
+                .symbolic_target()
+                .and_then(|target| target.strip_prefix("refs/heads/"))
+                .map(str::to_string));
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(head
+        .is_branch()
+        .then(|| head.shorthand().map(str::to_string))
+        .flatten())
+}
+
+/// Get the committer time of a revision, as seconds since the epoch and UTC offset in minutes
+pub fn commit_time(rev: &str) -> Result<(i64, i32), git2::Error> {
+    let repo = Repository::discover(".")?;
+    let time = repo.revparse_single(rev)?.peel_to_commit()?.time();
+
+    Ok((time.seconds(), time.offset_minutes()))
+}
+
+/// Get the message of the current HEAD commit
+pub fn head_message() -> Result<String, git2::Error> {
+    let repo = Repository::discover(".")?;
+    let head = repo.head()?.peel_to_commit()?;
+
+    Ok(head.message().unwrap_or_default().trim_end().to_string())
+}
+
+/// The configured committer identity as `Name <email>`.
+pub fn identity() -> Result<String, git2::Error> {
+    let repo = Repository::discover(".")?;
+    let signature = repo.signature()?;
+
+    Ok(format!(
+        "{} <{}>",
+        signature.name().unwrap_or_default(),
+        signature.email().unwrap_or_default()
+    ))
+}
+
+/// Replace the HEAD commit with one containing the staged changes and the given message.
+/// The original author and authored timestamp are kept unless `reset_author` is set.
+pub fn amend_head(commit_message: &str, reset_author: bool) -> Result<(), git2::Error> {
+    let repo = Repository::discover(".")?;
+    let head = repo.head()?.peel_to_commit()?;
+
+    // Write the index to a tree, so newly staged changes become part of the commit
+    let mut index = repo.index()?;
+    let tree_oid = index.write_tree()?;
+    let tree = repo.find_tree(tree_oid)?;
+
+    // The committer is always updated, just like `git commit --amend` does
+    let signature = repo.signature()?;
+    let author = reset_author.then_some(&signature);
+
+    head.amend(
+        Some("HEAD"),
+        author,
+        Some(&signature),
+        None,
+        Some(commit_message),
+        Some(&tree),
+    )?;
+
+    Ok(())
+}
 
 /// Commit staged changes with the given commit message
 pub fn commit_changes(commit_message: &str) -> Result<(), git2::Error> {
@@ -40,19 +141,19 @@ pub fn commit_changes(commit_message: &str) -> Result<(), git2::Error> {
                 eprintln!("Warning: HEAD has changed, attempting to get current HEAD");
 
                 // Try to get the current HEAD commit
-                if let Ok(head) = repo.head() {
-                    if let Ok(head_commit) = head.peel_to_commit() {
-                        // Try again with the current HEAD as parent
-                        repo.commit(
-                            Some("HEAD"),
-                            &signature,
-                            &signature,
-                            commit_message,
-                            &tree,
-                            &[&head_commit],
-                        )?;
-                        return Ok(());
-                    }
+                if let Ok(head) = repo.head()
+                    && let Ok(head_commit) = head.peel_to_commit()
+                {
+                    // Try again with the current HEAD as parent
+                    repo.commit(
+                        Some("HEAD"),
+                        &signature,
+                        &signature,
+                        commit_message,
+                        &tree,
+                        &[&head_commit],
+                    )?;
+                    return Ok(());
                 }
 
                 // If all else fails, try to commit without parents
@@ -74,3 +175,228 @@ pub fn commit_changes(commit_message: &str) -> Result<(), git2::Error> {
         }
     }
 }
+
+/// A commit from the repository history.
+#[derive(Debug, Clone)]
+pub struct HistoryCommit {
+    pub id: Oid,
+    pub message: String,
+    /// Whether the commit has more than one parent.
+    pub is_merge: bool,
+}
+
+impl HistoryCommit {
+    /// The abbreviated commit id, as shown by `git log --oneline`.
+    pub fn short_id(&self) -> String {
+        self.id.to_string()[..7].to_string()
+    }
+
+    /// The first line of the commit message.
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// List the commits in a revision range, oldest first.
+/// A single revision is treated as `<rev>..HEAD`.
+pub fn list_commits(range: &str) -> Result<Vec<HistoryCommit>, git2::Error> {
+    let repo = Repository::discover(".")?;
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    if range.contains("..") {
+        revwalk.push_range(range)?;
+    } else {
+        revwalk.push_head()?;
+        revwalk.hide(repo.revparse_single(range)?.peel_to_commit()?.id())?;
+    }
+
+    revwalk
+        .map(|id| {
+            let commit = repo.find_commit(id?)?;
+            Ok(HistoryCommit {
+                id: commit.id(),
+                message: commit.message().unwrap_or_default().trim_end().to_string(),
+                is_merge: commit.parent_count() > 1,
+            })
+        })
+        .collect()
+}
+
+/// Read the messages of up to `count` commits reachable from `start`, newest first.
+/// Merge commits are skipped, as their messages are generated by git.
+pub fn recent_messages(start: &str, count: usize) -> Result<Vec<String>, git2::Error> {
+    let repo = Repository::discover(".")?;
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk.push(repo.revparse_single(start)?.peel_to_commit()?.id())?;
+
+    let mut messages = Vec::new();
+    for id in revwalk {
+        let commit = repo.find_commit(id?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+
+        messages.push(commit.message().unwrap_or_default().trim_end().to_string());
+        if messages.len() == count {
+            break;
+        }
+    }
+
+    Ok(messages)
+}
+
+/// Rewrite the messages of the given commits, replaying every descendant up to HEAD, and
+/// return the new HEAD.
+///
+/// Trees, authors and the commit order stay untouched, so the working tree and index remain
+/// valid. HEAD has to be where it was when `backup_ref` was created by [`backup_head`].
+pub fn reword_commits(
+    messages: &HashMap<Oid, String>,
+    backup_ref: &str,
+) -> Result<Oid, git2::Error> {
+    let repo = Repository::discover(".")?;
+    let head = repo.head()?;
+    let head_commit = head.peel_to_commit()?;
+    check_backup(&repo, backup_ref, head_commit.id())?;
+
+    // Walk from HEAD down to the parents of the oldest commit being reworded
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    revwalk.push(head_commit.id())?;
+    for id in messages.keys() {
+        for parent in repo.find_commit(*id)?.parent_ids() {
+            if !messages.contains_key(&parent) {
+                revwalk.hide(parent)?;
+            }
+        }
+    }
+    let chain = revwalk.collect::<Result<Vec<_>, _>>()?;
+
+    // Only linear history can be replayed without redoing merges
+    for id in &chain {
+        if repo.find_commit(*id)?.parent_count() > 1 {
+            return Err(git2::Error::from_str(&format!(
+                "Cannot reword across merge commit {}",
+                id
+            )));
+        }
+    }
+
+    if let Some(missing) = messages.keys().find(|id| !chain.contains(id)) {
+        return Err(git2::Error::from_str(&format!(
+            "Commit {} is not an ancestor of HEAD",
+            missing
+        )));
+    }
+
+    // Replay the chain, pointing each commit at the rewritten version of its parents
+    let committer = repo.signature()?;
+    let mut rewritten: HashMap<Oid, Oid> = HashMap::new();
+    let mut new_head = head_commit.id();
+
+    for id in chain {
+        let commit = repo.find_commit(id)?;
+        let parents = commit
+            .parent_ids()
+            .map(|parent| repo.find_commit(*rewritten.get(&parent).unwrap_or(&parent)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parent_refs = parents.iter().collect::<Vec<_>>();
+
+        let message = match messages.get(&id) {
+            Some(message) => message.as_str(),
+            None => commit.message().unwrap_or_default(),
+        };
+
+        new_head = repo.commit(
+            None,
+            &commit.author(),
+            &committer,
+            message,
+            &commit.tree()?,
+            &parent_refs,
+        )?;
+        rewritten.insert(id, new_head);
+    }
+
+    // Move the current branch, or a detached HEAD, to the new tip
+    if head.is_branch() {
+        let branch = head
+            .name()
+            .ok_or_else(|| git2::Error::from_str("Branch name is not valid UTF-8"))?;
+        repo.reference(branch, new_head, true, "ai-commit: reword")?;
+    } else {
+        repo.set_head_detached(new_head)?;
+    }
+
+    Ok(new_head)
+}
+
+/// Replace every commit since the merge base with `base` by a single commit, like
+/// `git reset --soft <merge-base> && git commit` does, and return the new HEAD.
+/// HEAD has to be where it was when `backup_ref` was created by [`backup_head`].
+pub fn squash_onto(base: &str, commit_message: &str, backup_ref: &str) -> Result<Oid, git2::Error> {
+    let repo = Repository::discover(".")?;
+    let head_commit = repo.head()?.peel_to_commit()?;
+    check_backup(&repo, backup_ref, head_commit.id())?;
+    let base_commit = repo.revparse_single(base)?.peel_to_commit()?;
+    let merge_base = repo.find_commit(repo.merge_base(base_commit.id(), head_commit.id())?)?;
+
+    // Staged changes would silently end up in the squashed commit without being described
+    let index = repo.index()?;
+    let staged = repo.diff_tree_to_index(Some(&head_commit.tree()?), Some(&index), None)?;
+    if staged.deltas().len() > 0 {
+        return Err(git2::Error::from_str(
+            "There are staged changes, commit or unstage them before squashing",
+        ));
+    }
+
+    repo.reset(merge_base.as_object(), ResetType::Soft, None)?;
+    commit_changes(commit_message)?;
+
+    Ok(repo.head()?.peel_to_commit()?.id())
+}
+
+/// Point a new reference under `refs/ai-commit/backup/` at the current HEAD, so a rewrite can
+/// be rolled back. The name ends with the short id of HEAD, so rewrites in the same second get
+/// their own backup, and a backup of the same commit is reused.
+pub fn backup_head(operation: &str) -> Result<String, git2::Error> {
+    let repo = Repository::discover(".")?;
+    let head = repo.head()?;
+    let head_id = head.peel_to_commit()?.id();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let backup_ref = format!(
+        "refs/ai-commit/backup/{}-{}-{}",
+        head.shorthand().unwrap_or("HEAD"),
+        timestamp,
+        &head_id.to_string()[..7]
+    );
+
+    if repo.refname_to_id(&backup_ref).ok() != Some(head_id) {
+        repo.reference(
+            &backup_ref,
+            head_id,
+            false,
+            &format!("ai-commit: backup before {}", operation),
+        )?;
+    }
+
+    Ok(backup_ref)
+}
+
+/// Refuse to rewrite when HEAD moved since the backup was made, as the backup would not hold
+/// the history being replaced.
+fn check_backup(repo: &Repository, backup_ref: &str, head_id: Oid) -> Result<(), git2::Error> {
+    match repo.refname_to_id(backup_ref)? == head_id {
+        true => Ok(()),
+        false => Err(git2::Error::from_str(&format!(
+            "HEAD has moved since {} was saved, nothing was rewritten",
+            backup_ref
+        ))),
+    }
+}
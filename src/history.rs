@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use log::debug;
+use regex::Regex;
+
+use crate::git;
+
+/// The number of recent messages shown to the model as examples.
+const EXAMPLE_COUNT: usize = 5;
+
+/// Longer example bodies are cut to keep the prompt small.
+const EXAMPLE_MAX_LINES: usize = 12;
+
+/// Messages written by tools rather than people, which say nothing about the repository style.
+const GENERATED_PREFIXES: [&str; 4] = ["Merge ", "Revert \"", "fixup! ", "squash! "];
+
+/// The grammatical form of the first word of the summaries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tense {
+    /// "Add support for ..."
+    Imperative,
+    /// "Added support for ..."
+    Past,
+    /// "Adds support for ..."
+    ThirdPerson,
+}
+
+/// Conventions detected in the recent commit messages of the repository.
+#[derive(Debug, Clone)]
+pub struct Conventions {
+    /// The shape of the ticket reference that starts most summaries, e.g. `[ABC-123] `.
+    pub ticket_prefix: Option<String>,
+    pub tense: Tense,
+    /// Whether most summaries start with a capital letter.
+    pub capitalised: bool,
+    /// The median length of the summary lines.
+    pub typical_length: usize,
+    /// The percentage of commits that have a body.
+    pub body_percentage: usize,
+    /// Representative messages, used as few-shot examples.
+    pub examples: Vec<String>,
+}
+
+/// Learn the conventions of the last `count` commits reachable from `start`.
+/// Returns `None` when there is too little history to learn from or `count` is 0.
+pub fn learn(start: &str, count: usize) -> Option<Conventions> {
+    if count == 0 {
+        return None;
+    }
+
+    let messages = match git::recent_messages(start, count) {
+        Ok(messages) => messages,
+        Err(e) => {
+            debug!("Could not read the commit history: {}", e);
+            return None;
+        }
+    };
+    let messages = messages
+        .into_iter()
+        .filter(|message| {
+            !message.trim().is_empty()
+                && !GENERATED_PREFIXES
+                    .iter()
+                    .any(|prefix| message.starts_with(prefix))
+        })
+        .collect::<Vec<_>>();
+
+    let conventions = analyze(&messages);
+    debug!("Learned commit conventions: {:?}", conventions);
+
+    conventions
+}
+
+fn analyze(messages: &[String]) -> Option<Conventions> {
+    // A couple of commits, typically "Initial commit", do not make a style
+    if messages.len() < 3 {
+        return None;
+    }
+
+    let ticket =
+        Regex::new(r"^(\[?)[A-Z][A-Z0-9]+-[0-9]+(\]?:?\s+)").expect("Invalid ticket pattern");
+    let prefix =
+        Regex::new(r"^(\[?[A-Z][A-Z0-9]+-[0-9]+\]?:?\s+|[a-z]+(\([^)]*\))?!?:\s+|:[a-z_]+:\s+)+")
+            .expect("Invalid prefix pattern");
+
+    let summaries = messages
+        .iter()
+        .map(|message| message.lines().next().unwrap_or_default().trim())
+        .collect::<Vec<_>>();
+
+    let ticket_prefix = majority(summaries.iter().map(|summary| {
+        ticket.captures(summary).map(|captures| {
+            format!(
+                "{}ABC-123{}",
+                &captures[1],
+                &captures[2].replace(char::is_whitespace, " ")
+            )
+        })
+    }))
+    .flatten();
+
+    let subjects = summaries
+        .iter()
+        .map(|summary| prefix.replace(summary, "").to_string())
+        .filter(|subject| !subject.is_empty())
+        .collect::<Vec<_>>();
+    let tense =
+        majority(subjects.iter().map(|subject| tense_of(subject))).unwrap_or(Tense::Imperative);
+    let capitalised = subjects
+        .iter()
+        .filter(|subject| subject.starts_with(char::is_uppercase))
+        .count()
+        * 2
+        >= subjects.len();
+
+    let mut lengths = summaries
+        .iter()
+        .map(|summary| summary.chars().count())
+        .collect::<Vec<_>>();
+    lengths.sort_unstable();
+    let typical_length = lengths[lengths.len() / 2];
+
+    let with_body = messages
+        .iter()
+        .filter(|message| message.lines().skip(1).any(|line| !line.trim().is_empty()))
+        .count();
+    let body_percentage = with_body * 100 / messages.len();
+
+    Some(Conventions {
+        ticket_prefix,
+        tense,
+        capitalised,
+        typical_length,
+        body_percentage,
+        examples: examples(messages, typical_length),
+    })
+}
+
+/// The most common value, if it occurs in at least half of the items.
+fn majority<T: Eq + std::hash::Hash>(items: impl Iterator<Item = T>) -> Option<T> {
+    let mut counts = HashMap::new();
+    let mut total = 0;
+    for item in items {
+        *counts.entry(item).or_insert(0) += 1;
+        total += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .filter(|(_, count)| count * 2 >= total)
+        .map(|(item, _)| item)
+}
+
+/// Guess the tense from the first word of the subject.
+fn tense_of(subject: &str) -> Tense {
+    let word = subject
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .trim_matches(|c: char| !c.is_alphabetic())
+        .to_lowercase();
+
+    if word.len() > 3
+        && word.ends_with("ed")
+        && !["need", "embed", "speed", "seed", "feed"].contains(&word.as_str())
+    {
+        Tense::Past
+    } else if word.len() > 3
+        && word.ends_with('s')
+        && !word.ends_with("ss")
+        && !word.ends_with("us")
+        && !word.ends_with("is")
+    {
+        Tense::ThirdPerson
+    } else {
+        Tense::Imperative
+    }
+}
+
+/// Pick the messages whose summary length is closest to the typical one, keeping the newest first.
+fn examples(messages: &[String], typical_length: usize) -> Vec<String> {
+    let mut candidates = messages.iter().enumerate().collect::<Vec<_>>();
+    candidates.sort_by_key(|(index, message)| {
+        let length = message.lines().next().unwrap_or_default().chars().count();
+        (length.abs_diff(typical_length), *index)
+    });
+    candidates.truncate(EXAMPLE_COUNT);
+    candidates.sort_by_key(|(index, _)| *index);
+
+    candidates
+        .into_iter()
+        .map(|(_, message)| {
+            let lines = message.lines().collect::<Vec<_>>();
+            match lines.len() > EXAMPLE_MAX_LINES {
+                true => format!("{}\n...", lines[..EXAMPLE_MAX_LINES].join("\n")),
+                false => message.clone(),
+            }
+        })
+        .collect()
+}
@@ -1,29 +1,52 @@
 use std::process;
 
-use api::provider::Provider;
+use api::provider::{Provider, ToolSpec};
 use clap::Parser;
 use commit_editor::edit_message;
-use dialoguer::console::{self, Style};
+use dialoguer::console;
 use env_logger::Builder;
-use log::{error, info, trace};
+use log::{debug, error, info, trace};
 
 mod api;
+mod changelog;
 mod cli;
 mod cli_config;
 mod commit_editor;
+mod config_command;
+mod config_layers;
 mod config_manager;
 mod cost_estimation;
+mod credentials;
 mod diff;
 mod git;
+mod history;
 mod ignore;
+mod payload;
+mod policy;
+mod pr;
 mod prompt;
+mod redact;
+mod repo_config;
+mod reword;
+mod secrets;
+mod settings;
+mod squash;
+mod style;
+mod template;
+mod ticket;
+mod trailers;
+mod warnings;
 
 use cli::UserChoice;
-use cli_config::CliConfig;
+use cli_config::{CliConfig, Command};
+use diff::DiffSource;
+use payload::{Outcome, Payload};
+use prompt::PromptVariables;
+use secrets::SecretScan;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse the command-line arguments
-    let cli_config = CliConfig::parse();
+    let mut cli_config = CliConfig::parse();
     let terminal = console::Term::stdout();
 
     // Initialize the logger with a default level
@@ -33,6 +56,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     trace!("Starting ai-commit!");
     trace!("Parsed cli args: {:?}", &cli_config);
 
+    let source = cli_config.diff_source();
+    let reset_author = cli_config.reset_author;
+    // The settings can be inspected and changed even while they are incomplete
+    let command = match cli_config.command.take() {
+        Some(Command::Config { action }) => {
+            if let Err(e) = config_command::run(&cli_config, &action) {
+                error!("{}", e);
+                process::exit(1);
+            }
+            return Ok(());
+        }
+        command => command,
+    };
+
     // Load existing configuration or use defaults
     let config = match config_manager::load_config(cli_config) {
         Ok(config) => config,
@@ -43,83 +80,215 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    terminal.write_line(&format!("Using model: {}", config.get_model()))?;
+    match &config.profile {
+        Some(profile) => terminal.write_line(&format!(
+            "Using model: {} (profile {})",
+            config.get_model(),
+            profile
+        ))?,
+        None => terminal.write_line(&format!("Using model: {}", config.get_model()))?,
+    }
+
+    if let Some(command) = command {
+        return match command {
+            Command::Reword { range } => reword::run(&config, &range),
+            Command::Squash { base, commit } => squash::run(&config, &base, commit),
+            Command::Pr { base } => pr::run(&config, &base),
+            Command::Changelog {
+                range,
+                release,
+                prepend,
+            } => changelog::run(&config, &range, release.as_deref(), prepend.as_deref()),
+            Command::Config { .. } => unreachable!("Handled before loading the configuration"),
+        };
+    }
 
-    // Retrieve the staged diff
-    let diff = match git::get_staged_diff(config.context_lines) {
+    // Retrieve the diff for the selected source
+    let diff = match git::get_diff(&source, config.diff_settings()) {
         Ok(diff) => {
             if diff.is_empty() {
-                terminal.write_line("No staged changes found")?;
+                terminal.write_line(&format!("No {} found", source.describe()))?;
                 process::exit(0);
             }
             diff
         }
         Err(e) => {
             error!("{}", e);
-            terminal.write_line("Error retrieving staged changes")?;
+            terminal.write_line(&format!("Error retrieving {}", source.describe()))?;
             process::exit(1);
         }
     };
 
-    trace!("Staged diff: \n{}", &diff);
+    for file in &diff.files {
+        debug!(
+            "{} {:?} (+{} -{}, language: {}, binary: {})",
+            file.path().display(),
+            file.status,
+            file.insertions(),
+            file.deletions(),
+            file.language.unwrap_or("unknown"),
+            file.is_binary
+        );
+    }
 
-    // Get the prompt for the model input
-    let prompt = format!("{}\n\nDiff:\n{}", prompt::get_system_prompt(), &diff);
-    // Estimate cost before proceeding
-    let cost = cost_estimation::estimate_cost(config.get_model(), &prompt)?;
+    // Look for secrets before anything leaves the machine, unless only metadata is sent
+    let Some((findings, redactor)) = payload::screen(&config, [&diff], config.privacy) else {
+        process::exit(1);
+    };
 
-    println!("{}", cost_estimation::format_cost_estimate(&cost));
+    // Render the structured diff back to a unified patch for the model
+    let patch = diff.render(config.privacy);
+
+    trace!("Staged diff: \n{}", &patch);
+
+    // When amending, the existing message gives the model prior context
+    let previous_message = match source {
+        DiffSource::Amend => match git::head_message() {
+            Ok(message) => Some(message),
+            Err(e) => {
+                error!("{}", e);
+                terminal.write_line("Error reading the commit to amend")?;
+                process::exit(1);
+            }
+        },
+        _ => None,
+    };
+
+    // Get the prompt for the model input
+    let style = config.message_style();
+    let scope = style.scope_for(&diff, &config.scopes);
+    let ticket = ticket::detect(&config.ticket, config.get_user_desc());
+    let variables = PromptVariables::new(&diff, config.get_user_desc(), ticket.as_deref());
+    let trailers = match trailers::collect(&config, &style, ticket.as_deref()) {
+        Ok(trailers) => trailers,
+        Err(e) => {
+            error!("{}", e);
+            terminal.write_line("Error preparing the commit trailers")?;
+            process::exit(1);
+        }
+    };
+    let template = config.template("commit");
+    let mut system_prompt =
+        prompt::get_system_prompt(&template, &style, config.prompt.as_ref(), &variables);
+    // The commit being amended is replaced, so it is not part of the history to learn from
+    let history_start = match source {
+        DiffSource::Amend => "HEAD^",
+        _ => "HEAD",
+    };
+    let conventions = history::learn(history_start, config.history_commits);
+    if let Some(conventions) = &conventions {
+        prompt::append_conventions(&mut system_prompt, conventions);
+    }
+    let mut content = prompt::build_user_content(
+        &template,
+        &patch,
+        config.privacy,
+        config.get_user_desc(),
+        previous_message.as_deref(),
+    );
+    prompt::append_scope_hint(&mut content, scope.as_ref());
 
-    // Generate the initial commit message suggestion
-    if !cli::prompt_for_confirmation("Do you want to proceed?") {
-        terminal.write_line("Operation canceled by the user")?;
-        process::exit(0);
+    // Redact, estimate the cost and ask before anything is sent
+    let mut payloads = [Payload {
+        label: None,
+        system_prompt,
+        content,
+        diff: &diff,
+    }];
+    let tool = ToolSpec::commit_message(&style);
+    match payload::prepare(
+        &config,
+        &redactor,
+        &tool,
+        "Do you want to proceed?",
+        &mut payloads,
+    ) {
+        Ok(Outcome::Send) => {}
+        Ok(Outcome::DryRun) => return Ok(()),
+        Ok(Outcome::Canceled) => process::exit(0),
+        Err(e) => {
+            error!("{}", e);
+            terminal.write_line("Operation canceled, no changes were sent")?;
+            process::exit(1);
+        }
     }
+    let [payload] = payloads;
 
     let (provider, key) = config.get_provider_key();
-    let api = Provider::create_provider(provider, key).expect("Failed to create provider");
+    let api = Provider::create_provider(provider, key, config.base_url.as_deref())
+        .expect("Failed to create provider");
 
     terminal.write_line("Generating commit message...")?;
 
-    let (mut commit_message, warning_message) =
-        match api.generate_commit_message(config.get_model(), &diff, config.get_user_desc()) {
-            Ok(msg) => (
-                format!(
-                    "{}{}",
-                    msg.summary,
-                    match msg.description {
-                        Some(desc) => format!("\n\n{}", desc),
-                        None => "".to_string(),
-                    }
-                ),
-                msg.warning,
+    let (mut commit_message, warnings) = match api.generate_commit_message(
+        config.get_model(),
+        &style,
+        &payload.system_prompt,
+        &payload.content,
+    ) {
+        Ok(msg) => (
+            style.format_message(&msg, scope.as_ref()),
+            policy::filter_allowed(
+                warnings::verify(msg.warnings, &diff),
+                &diff,
+                &config.allowlist,
             ),
-            Err(_) => {
-                process::exit(1);
-            }
-        };
+        ),
+        Err(_) => {
+            process::exit(1);
+        }
+    };
+
+    if let Some(reference) = &ticket {
+        commit_message = ticket::apply(
+            &commit_message,
+            &style,
+            reference,
+            config.ticket.placement,
+            conventions
+                .as_ref()
+                .and_then(|c| c.ticket_prefix.as_deref()),
+        );
+    }
+    commit_message = trailers::append(&commit_message, &trailers);
 
     terminal.write_line(&commit_message)?;
 
-    if let Some(warning) = warning_message {
-        let warning_style = Style::new().white().bold().on_red();
-        terminal.write_line(&warning_style.apply_to(warning).to_string())?;
+    warnings::print(&terminal, &warnings)?;
+
+    // Existing history is only described, there is nothing to commit
+    if !source.is_committable() {
+        return Ok(());
     }
 
-    handle_commit_message(&mut commit_message)?;
+    // Enforce the repository policy on the warnings and the local findings
+    let findings = match config.secret_scan {
+        SecretScan::Off => Vec::new(),
+        _ => findings,
+    };
+    if !policy::enforce(config.warning_policy, &policy::merge(warnings, &findings)) {
+        terminal.write_line("Nothing was committed")?;
+        process::exit(1);
+    }
+
+    handle_commit_message(&mut commit_message, &source, reset_author)?;
 
     terminal.write_line("Changes commited successfully")?;
 
     Ok(())
 }
 
-fn handle_commit_message(commit_message: &mut String) -> Result<(), std::io::Error> {
+fn handle_commit_message(
+    commit_message: &mut String,
+    source: &DiffSource,
+    reset_author: bool,
+) -> Result<(), std::io::Error> {
     match cli::prompt_user_for_action() {
         UserChoice::Edit => {
             info!("User chose to edit the commit message.");
             edit_message(commit_message)?;
 
-            if let Err(e) = git::commit_changes(commit_message) {
+            if let Err(e) = commit(commit_message, source, reset_author) {
                 error!("Failed to commit changes: {}", e);
             }
 
@@ -128,7 +297,7 @@ fn handle_commit_message(commit_message: &mut String) -> Result<(), std::io::Err
         UserChoice::Commit => {
             info!("User accepted the commit message.");
 
-            if let Err(e) = git::commit_changes(commit_message) {
+            if let Err(e) = commit(commit_message, source, reset_author) {
                 error!("Failed to commit changes: {}", e);
             }
 
@@ -140,3 +309,19 @@ fn handle_commit_message(commit_message: &mut String) -> Result<(), std::io::Err
         }
     }
 }
+
+fn commit(
+    commit_message: &str,
+    source: &DiffSource,
+    reset_author: bool,
+) -> Result<(), git2::Error> {
+    match source {
+        DiffSource::Amend => git::amend_head(commit_message, reset_author),
+        DiffSource::WorkingTree => {
+            // Mirror `git commit -a` by staging tracked changes right before committing
+            git::stage_tracked_changes()?;
+            git::commit_changes(commit_message)
+        }
+        _ => git::commit_changes(commit_message),
+    }
+}
@@ -0,0 +1,242 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use dialoguer::console::{Style, Term};
+use log::{debug, trace};
+
+use crate::api::provider::{Provider, ToolSpec};
+use crate::cli::{self, ProceedChoice};
+use crate::config_manager::AppConfig;
+use crate::cost_estimation;
+use crate::diff::{Privacy, StagedChange};
+use crate::redact::Redactor;
+use crate::secrets::{self, Finding};
+
+/// The file a dry run writes to when `--dry-run` is given without a path.
+pub const DEFAULT_DRY_RUN_FILE: &str = "ai-commit-request.json";
+
+/// A prompt to send to the provider. After `prepare`, it is exactly what is sent.
+pub struct Payload<'a> {
+    /// The commit the prompt is for, when several prompts are sent.
+    pub label: Option<String>,
+    pub system_prompt: String,
+    pub content: String,
+    pub diff: &'a StagedChange,
+}
+
+/// What `prepare` decided about the payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Send,
+    /// The payloads were written to the `--dry-run` file instead.
+    DryRun,
+    Canceled,
+}
+
+/// Scan the changes for secrets before anything leaves the machine and let the user review
+/// them, unless only metadata is sent. Returns the findings and a redactor for the secrets and
+/// personal data in the changes, or `None` when the user cancels.
+pub fn screen<'a>(
+    config: &AppConfig,
+    diffs: impl IntoIterator<Item = &'a StagedChange>,
+    privacy: Privacy,
+) -> Option<(Vec<Finding>, Redactor)> {
+    let mut findings = Vec::new();
+    let mut redactor = Redactor::new(&config.redaction);
+    for diff in diffs {
+        let diff_findings = secrets::scan(diff, &config.allowlist);
+        redactor.collect(diff, &diff_findings);
+        findings.extend(diff_findings);
+    }
+
+    if privacy != Privacy::StatOnly && !secrets::review(&findings, config.secret_scan) {
+        let _ = Term::stdout().write_line("Operation canceled, no changes were sent");
+        return None;
+    }
+
+    Some((findings, redactor))
+}
+
+/// Make the payloads ready to send: replace secrets and personal data, estimate the cost and
+/// check the limits, then write them to the `--dry-run` file or ask whether to send them. Every
+/// command sends its prompts through here, so none can skip a step.
+pub fn prepare(
+    config: &AppConfig,
+    redactor: &Redactor,
+    tool: &ToolSpec,
+    confirmation: &str,
+    payloads: &mut [Payload],
+) -> Result<Outcome, Box<dyn Error>> {
+    let terminal = Term::stdout();
+
+    let (mut total_tokens, mut total_cost) = (0, 0.0);
+    for payload in payloads.iter_mut() {
+        payload.system_prompt = redactor.apply(&payload.system_prompt);
+        payload.content = redactor.apply(&payload.content);
+
+        let (tokens, cost) = cost_estimation::estimate_cost(
+            config.get_model(),
+            &format!("{}\n\n{}", payload.system_prompt, payload.content),
+        )?;
+        total_tokens += tokens;
+        total_cost += cost;
+    }
+    if !redactor.is_empty() {
+        terminal.write_line(&format!(
+            "Redacted {} values from the prompt",
+            redactor.len()
+        ))?;
+    }
+
+    let estimate = (total_tokens, total_cost);
+    println!("{}", cost_estimation::format_cost_estimate(&estimate));
+    cost_estimation::check_limits(&estimate, config.max_prompt_tokens, config.max_cost)?;
+
+    if let Some(path) = &config.dry_run {
+        write_dry_run(path, config, tool, payloads)?;
+        terminal.write_line(&format!(
+            "Wrote the request to {}, nothing was sent",
+            path.display()
+        ))?;
+        return Ok(Outcome::DryRun);
+    }
+
+    match confirm(confirmation, payloads) {
+        true => Ok(Outcome::Send),
+        false => {
+            terminal.write_line("Operation canceled by the user")?;
+            Ok(Outcome::Canceled)
+        }
+    }
+}
+
+/// Ask whether to send the payloads, paging them as often as the user asks to view them.
+fn confirm(confirmation: &str, payloads: &[Payload]) -> bool {
+    loop {
+        match cli::prompt_to_proceed(confirmation) {
+            ProceedChoice::Proceed => return true,
+            ProceedChoice::Cancel => return false,
+            ProceedChoice::ViewPayload => {
+                if let Err(e) = page(&render(payloads)) {
+                    debug!("Failed to page the payload: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// Describe the payloads for review: the files that were sent and left out, then the prompts.
+fn render(payloads: &[Payload]) -> String {
+    let heading = Style::new().bold();
+    let excluded = Style::new().yellow().bold();
+    let mut output = String::new();
+
+    for payload in payloads {
+        if let Some(label) = &payload.label {
+            let _ = writeln!(
+                output,
+                "{}\n",
+                heading.apply_to(format!("=== {} ===", label))
+            );
+        }
+
+        let _ = writeln!(
+            output,
+            "{}",
+            heading.apply_to(format!("Included files ({})", payload.diff.files.len()))
+        );
+        for file in &payload.diff.files {
+            let _ = writeln!(output, "  {}", file.path().display());
+        }
+        if !payload.diff.ignored.is_empty() {
+            let _ = writeln!(
+                output,
+                "{}",
+                excluded.apply_to(format!(
+                    "Excluded by .ai-commit-ignore ({})",
+                    payload.diff.ignored.len()
+                ))
+            );
+            for path in &payload.diff.ignored {
+                let _ = writeln!(output, "  {}", excluded.apply_to(path.display()));
+            }
+        }
+
+        let _ = writeln!(
+            output,
+            "\n{}\n{}\n\n{}\n{}\n",
+            heading.apply_to("System prompt"),
+            payload.system_prompt,
+            heading.apply_to("User content"),
+            payload.content
+        );
+    }
+
+    output
+}
+
+/// Show the text in `$PAGER`, or `less` by default. Without a terminal or a working pager, the
+/// text is printed instead.
+fn page(text: &str) -> std::io::Result<()> {
+    let terminal = Term::stdout();
+    if !terminal.is_term() {
+        return terminal.write_str(text);
+    }
+
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    trace!("Using '{}' pager", pager);
+    let mut command = Command::new(&pager);
+    // Like git, let less pass colors through and quit when the text fits on one screen
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    match command.stdin(Stdio::piped()).spawn() {
+        Ok(mut child) => {
+            if let Some(mut stdin) = child.stdin.take() {
+                // The pager closes its input when the user quits early
+                let _ = stdin.write_all(text.as_bytes());
+            }
+            child.wait()?;
+            Ok(())
+        }
+        Err(e) => {
+            debug!("Failed to start pager '{}': {}", pager, e);
+            terminal.write_str(text)
+        }
+    }
+}
+
+/// Write the request bodies the provider would be sent to the file, without sending them. A single
+/// request is written as is, several as an array.
+fn write_dry_run(
+    path: &Path,
+    config: &AppConfig,
+    tool: &ToolSpec,
+    payloads: &[Payload],
+) -> Result<(), Box<dyn Error>> {
+    let mut requests = payloads
+        .iter()
+        .map(|payload| {
+            Provider::request_body(
+                config.get_provider(),
+                config.get_model(),
+                &payload.system_prompt,
+                &payload.content,
+                tool,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let json = match requests.len() {
+        1 => serde_json::to_string_pretty(&requests.remove(0))?,
+        _ => serde_json::to_string_pretty(&requests)?,
+    };
+    fs::write(path, json + "\n")?;
+
+    Ok(())
+}
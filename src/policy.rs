@@ -0,0 +1,180 @@
+use std::fs;
+use std::path::Path;
+
+use dialoguer::console::{Style, Term};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::debug;
+use serde::Deserialize;
+
+use crate::cli;
+use crate::diff::StagedChange;
+use crate::secrets::Finding;
+use crate::warnings::{Severity, Warning, WarningKind};
+
+/// The allowlist file, checked in at the repository root.
+pub const ALLOWLIST_FILE: &str = ".ai-commit-allowlist";
+
+/// Added lines containing this marker, usually in a comment, are never reported.
+pub const ALLOW_MARKER: &str = "ai-commit: allow";
+
+/// What happens when the changes contain possible sensitive information.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningPolicy {
+    /// Show the warnings and offer to commit as usual.
+    #[default]
+    Warn,
+    /// Ask for confirmation before offering to commit.
+    Confirm,
+    /// Refuse to commit until the lines are unstaged or allowlisted.
+    Block,
+}
+
+/// The `[policy]` section of the repository config.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    pub warnings: WarningPolicy,
+}
+
+/// Files whose sensitive information is accepted, e.g. test fixtures with fake keys.
+#[derive(Debug, Clone)]
+pub struct Allowlist {
+    globs: GlobSet,
+}
+
+impl Default for Allowlist {
+    fn default() -> Self {
+        Allowlist {
+            globs: GlobSet::empty(),
+        }
+    }
+}
+
+impl Allowlist {
+    /// Load the allowlist file from the repository root, with one glob per line and `#` comments.
+    /// Without the file, only the inline marker allows lines.
+    pub fn load(repo_path: &Path) -> Result<Self, String> {
+        let path = repo_path.join(ALLOWLIST_FILE);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Ok(Allowlist::default()),
+        };
+
+        let mut builder = GlobSetBuilder::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let glob =
+                Glob::new(line).map_err(|e| format!("Invalid {} entry: {}", ALLOWLIST_FILE, e))?;
+            builder.add(glob);
+        }
+        let globs = builder
+            .build()
+            .map_err(|e| format!("Invalid {}: {}", ALLOWLIST_FILE, e))?;
+        debug!("Loaded {} allowlist entries", globs.len());
+
+        Ok(Allowlist { globs })
+    }
+
+    /// Whether the file is allowlisted, or the line contains the inline marker.
+    pub fn allows(&self, path: &Path, content: Option<&str>) -> bool {
+        self.globs.is_match(path) || content.is_some_and(|content| content.contains(ALLOW_MARKER))
+    }
+}
+
+/// Combine the warnings of the model with the local secret findings, so both are enforced.
+/// A finding on a line the model already reported raises the severity of that warning.
+pub fn merge(mut warnings: Vec<Warning>, findings: &[Finding]) -> Vec<Warning> {
+    for finding in findings {
+        let file = finding.file.display().to_string();
+        let existing = warnings.iter_mut().find(|warning| {
+            warning.file.as_deref() == Some(file.as_str()) && warning.line == Some(finding.line)
+        });
+
+        match existing {
+            Some(warning) => {
+                warning.kind = WarningKind::Secret;
+                warning.severity = Severity::High;
+            }
+            None => warnings.push(Warning {
+                file: Some(file),
+                line: Some(finding.line),
+                kind: WarningKind::Secret,
+                severity: Severity::High,
+                message: format!("Possible {}", finding.description),
+            }),
+        }
+    }
+
+    warnings
+}
+
+/// Drop the warnings for allowlisted files and lines marked with [`ALLOW_MARKER`].
+pub fn filter_allowed(
+    warnings: Vec<Warning>,
+    change: &StagedChange,
+    allowlist: &Allowlist,
+) -> Vec<Warning> {
+    warnings
+        .into_iter()
+        .filter(|warning| {
+            let Some(file) = &warning.file else {
+                return true;
+            };
+            let content = change
+                .files
+                .iter()
+                .find(|changed| changed.path() == Path::new(file))
+                .and_then(|changed| {
+                    changed
+                        .added_lines()
+                        .find(|(line, _)| Some(*line) == warning.line)
+                        .map(|(_, content)| content)
+                });
+
+            let allowed = allowlist.allows(Path::new(file), content);
+            if allowed {
+                debug!("Allowlisted warning: {}", warning);
+            }
+            !allowed
+        })
+        .collect()
+}
+
+/// Apply the policy to the remaining warnings. Returns `true` when the commit may proceed.
+///
+/// Under `block`, warnings that point at a file refuse the commit, while warnings without a
+/// location cannot be unstaged or allowlisted and ask for confirmation instead. Without a
+/// terminal, confirmation is never given, so gated commits fail in scripts and hooks.
+pub fn enforce(policy: WarningPolicy, warnings: &[Warning]) -> bool {
+    if warnings.is_empty() || policy == WarningPolicy::Warn {
+        return true;
+    }
+
+    let terminal = Term::stdout();
+    let error_style = Style::new().red().bold();
+    let located = warnings
+        .iter()
+        .filter(|warning| warning.file.is_some())
+        .collect::<Vec<_>>();
+
+    if policy == WarningPolicy::Block && !located.is_empty() {
+        let _ = terminal.write_line(
+            &error_style
+                .apply_to("Commit refused by the repository policy. Unstage these lines or allowlist them:")
+                .to_string(),
+        );
+        for warning in located {
+            let _ = terminal.write_line(&format!("  {}", warning));
+        }
+        let _ = terminal.write_line(&format!(
+            "Add '{}' to a line, or a glob for the file to {}.",
+            ALLOW_MARKER, ALLOWLIST_FILE
+        ));
+        return false;
+    }
+
+    cli::prompt_for_confirmation("The changes may contain sensitive information. Commit anyway?")
+}
@@ -0,0 +1,151 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use dialoguer::console::Term;
+use log::{debug, error, trace};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::api::provider::{Provider, ProviderError, ToolSpec};
+use crate::config_manager::AppConfig;
+use crate::diff::DiffSource;
+use crate::git;
+use crate::payload::{self, Outcome, Payload};
+use crate::prompt;
+use crate::template::Context;
+
+/// Locations GitHub looks for a pull request template, relative to the repository root.
+const TEMPLATE_PATHS: [&str; 4] = [
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "docs/pull_request_template.md",
+];
+
+#[derive(Deserialize)]
+struct PullRequest {
+    title: String,
+    body: String,
+}
+
+/// The tool used to generate pull request descriptions.
+fn pull_request_tool() -> ToolSpec {
+    ToolSpec {
+        name: "pull_request",
+        description: "Generate a pull request title and description from a branch diff",
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "title": {
+                    "type": "string",
+                    "description": "A one-line pull request title, starting with a capital letter."
+                },
+                "body": {
+                    "type": "string",
+                    "description": "The pull request description in GitHub-flavoured markdown."
+                }
+            },
+            "required": ["title", "body"],
+            "additionalProperties": false
+        }),
+    }
+}
+
+/// Generate a pull request title and description for the current branch against `base`.
+pub fn run(config: &AppConfig, base: &str) -> Result<(), Box<dyn Error>> {
+    let terminal = Term::stdout();
+
+    let commits = git::list_commits(&format!("{}..HEAD", base))?;
+    if commits.is_empty() {
+        terminal.write_line(&format!("No commits found since {}", base))?;
+        return Ok(());
+    }
+
+    // The combined change of the branch, relative to where it diverged from the base
+    let diff = git::get_diff(
+        &DiffSource::Range(format!("{}...HEAD", base)),
+        config.diff_settings(),
+    )?;
+    if diff.is_empty() {
+        terminal.write_line(&format!("No changes found since {}", base))?;
+        return Ok(());
+    }
+
+    // Look for secrets before anything leaves the machine
+    let Some((_, redactor)) = payload::screen(config, [&diff], config.privacy) else {
+        return Ok(());
+    };
+
+    let patch = diff.render(config.privacy);
+    trace!("Branch diff: \n{}", &patch);
+
+    let branch = git::current_branch()?;
+    let pr_template = find_template(&git::repo_root()?);
+    let messages = commits
+        .iter()
+        .map(|commit| commit.message.clone())
+        .collect::<Vec<_>>();
+
+    let template = config.template("pr");
+    let system_prompt = template.system(&Context::default());
+    let content = prompt::build_pr_content(
+        &template,
+        &patch,
+        config.privacy,
+        config.get_user_desc(),
+        branch.as_deref(),
+        &messages,
+        pr_template.as_deref(),
+    );
+
+    let mut payloads = [Payload {
+        label: None,
+        system_prompt,
+        content,
+        diff: &diff,
+    }];
+    let outcome = payload::prepare(
+        config,
+        &redactor,
+        &pull_request_tool(),
+        "Do you want to proceed?",
+        &mut payloads,
+    )?;
+    if outcome != Outcome::Send {
+        return Ok(());
+    }
+    let [payload] = payloads;
+
+    let (provider, key) = config.get_provider_key();
+    let api = Provider::create_provider(provider, key, config.base_url.as_deref())?;
+
+    terminal.write_line("Generating pull request description...")?;
+
+    let arguments = api.call_tool(
+        config.get_model(),
+        &payload.system_prompt,
+        &payload.content,
+        &pull_request_tool(),
+    )?;
+    let pull_request = serde_json::from_value::<PullRequest>(arguments).map_err(|e| {
+        error!("Failed to parse pull request: {}", e);
+        ProviderError::InvalidFormat
+    })?;
+
+    terminal.write_line(&format!(
+        "\n{}\n\n{}",
+        pull_request.title, pull_request.body
+    ))?;
+
+    Ok(())
+}
+
+/// Read the repository's pull request template, if it has one.
+fn find_template(repo_root: &Path) -> Option<String> {
+    TEMPLATE_PATHS.iter().find_map(|path| {
+        let content = fs::read_to_string(repo_root.join(path)).ok()?;
+        debug!("Using pull request template {}", path);
+        Some(content)
+    })
+}
@@ -1,119 +1,207 @@
-pub fn get_system_prompt() -> String {
-    String::from(
-        r#"You are a Git commit message generator. Analyze provided Git diffs and create structured commit messages in JSON format, optionally utilizing user-supplied descriptions and detecting sensitive information.
-
-## Input Format:
-
-You will receive:
-- A Git diff in standard format
-- Optionally, a user-provided description of the change
-
-## Diff Parsing Rules:
-
-Line prefixes to interpret:
-- Lines starting with "+" (but NOT "+++") = newly added content
-- Lines starting with "-" (but NOT "---") = removed content
-- Lines starting with "@@" = location markers
-- Lines without prefix = unchanged context
-- Lines with "diff --git" = file headers
-
-## Output Format:
-
-Return a JSON object with exactly three fields in this order:
-- `summary`: A single-sentence overview of the key change (string)
-- `description`: A markdown dashed list of changes as a single string, or null (string|null)
-- `warning`: Sensitive information warning(s), or null. Use a string for one warning, an array for multiple, or null if none (string|array|null)
-
-### Examples:
-
-Simple change example:
-```json
-{
-  "summary": "JIRA-123 Add ticket number detection to commit message prompt",
-  "description": null,
-  "warning": null
-}
-```
-
-Complex change with multiple warnings:
-```json
-{
-  "summary": "JIRA-456 Refactor payment module and update documentation",
-  "description": "- Improved error handling in the payment module\n- Added README section on API usage\n- Replaced deprecated hashing algorithm",
-  "warning": [
-    "Possible password value detected in src/settings.py, line 45.",
-    "Possible API token detected in configs/.env, line 12."
-  ]
+use crate::diff::{Privacy, StagedChange};
+use crate::git;
+use crate::history::{Conventions, Tense};
+use crate::repo_config::{CustomPrompt, PromptMode};
+use crate::style::{InferredScope, MessageStyle};
+use crate::template::{self, Context, Template};
+
+/// Values available to repository prompts as `{{name}}` placeholders.
+#[derive(Debug, Default)]
+pub struct PromptVariables {
+    pub branch: Option<String>,
+    pub ticket: Option<String>,
+    pub files: Vec<String>,
+    pub user_description: Option<String>,
 }
-```
-
-### Warning field details:
-
-- Use a string if exactly one warning is found, an array of strings for two or more, or null if none.
-- If input is malformed (missing diff or description), set all fields (summary, description, warning) to null.
-
-## Change Significance
-
-Ignore trivial changes that don't affect functionality or user experience:
-- Whitespace adjustments (indentation, line breaks, trailing newlines)
-- Code formatting/style changes (line wrapping, bracket positioning)
-- Comment formatting
-- Import reordering without additions/removals
-
-Only document changes that have semantic meaning or technical impact.
 
-## Summary Guidelines
-
-- Extract ticket numbers matching [A-Z]+-[0-9]+ from the user description, if present.
-- If found, prepend the ticket number to the summary: "JIRA-123 Refactor authentication module"
-- If not, start with a capital letter and describe the change.
-- Focus on the primary purpose and impact, not implementation details.
-- For pure formatting changes, use simple descriptions like "Update code formatting" or "Improve code readability"
-
-## Description Guidelines
-
-- Use description: null for:
-  - Changes fully covered by the summary
-  - Pure formatting/whitespace changes
-  - Single-purpose changes (one functional modification)
-
-- Only include a description when the change has multiple distinct **semantic** aspects (2+ functional changes).
-
-- When description is needed:
-  - Use a markdown dashed list (maximum five bullets)
-  - Each bullet must describe a change that affects behavior, features, or architecture
-  - Be concise and focus on purpose or user impact
-  - Avoid mentioning code artifacts except for essential context
-
-- Never repeat the ticket number in the description.
-- Never document formatting, whitespace, or style changes in the description bullets.
+impl PromptVariables {
+    /// Collect the variables for a change and its detected ticket reference.
+    pub fn new(
+        change: &StagedChange,
+        user_description: Option<&str>,
+        ticket: Option<&str>,
+    ) -> Self {
+        PromptVariables {
+            branch: git::current_branch().ok().flatten(),
+            ticket: ticket.map(str::to_string),
+            files: change
+                .files
+                .iter()
+                .map(|file| file.path().display().to_string())
+                .collect(),
+            user_description: user_description.map(str::to_string),
+        }
+    }
+
+    /// The variables as a template context.
+    pub fn context(&self) -> Context {
+        let mut context = Context::default();
+        context
+            .set("branch", self.branch.as_deref().unwrap_or_default())
+            .set("ticket", self.ticket.as_deref().unwrap_or_default())
+            .set("files", self.files.join(", "))
+            .set(
+                "user_description",
+                self.user_description.as_deref().unwrap_or_default(),
+            );
+
+        context
+    }
+
+    /// Substitute the `{{name}}` placeholders in the text. Unknown placeholders are kept as they are.
+    pub fn render(&self, text: &str) -> String {
+        template::render(text, &self.context())
+    }
+}
 
-## Sensitive Information Detection
+/// The system prompt for commit messages, rendered from the `commit` or `review` template.
+/// A repository prompt is either appended to the template or replaces it; the style
+/// instructions are always added.
+pub fn get_system_prompt(
+    template: &Template,
+    style: &MessageStyle,
+    custom: Option<&CustomPrompt>,
+    variables: &PromptVariables,
+) -> String {
+    let mut prompt = match custom {
+        Some(custom) if custom.mode == PromptMode::Replace => variables.render(&custom.text),
+        _ => template.system(&variables.context()),
+    };
+
+    if let Some(custom) = custom
+        && custom.mode == PromptMode::Append
+    {
+        prompt.push_str("\n\n## Repository Instructions\n\n");
+        prompt.push_str(&variables.render(&custom.text));
+    }
+
+    if let Some(instructions) = style.prompt_instructions() {
+        prompt.push_str("\n\n");
+        prompt.push_str(&instructions);
+    }
+
+    prompt
+}
 
-- Examine ONLY newly added lines (start with '+', but not '+++ filename' headers).
-- Scan for: passwords or credentials, private keys/certificates, credit card or bank numbers, API tokens/secrets, secret configuration values, personal contact info (email, phone numbers).
-- For each, add an entry like "Possible API token detected in config/secrets.yml, line 23." Include file path, secret type, and line number.
+/// Append the conventions learned from the repository history to the system prompt.
+pub fn append_conventions(prompt: &mut String, conventions: &Conventions) {
+    prompt.push_str(
+        "\n\n## Repository Conventions\n\nThe recent commits of this repository follow these conventions. They take precedence over the generic examples above:",
+    );
+
+    prompt.push_str(match conventions.tense {
+        Tense::Imperative => "\n- Summaries use the imperative mood (\"Add\", \"Fix\").",
+        Tense::Past => "\n- Summaries use the past tense (\"Added\", \"Fixed\").",
+        Tense::ThirdPerson => "\n- Summaries use the third person (\"Adds\", \"Fixes\").",
+    });
+    prompt.push_str(match conventions.capitalised {
+        true => "\n- Summaries start with a capital letter.",
+        false => "\n- Summaries start with a lowercase letter.",
+    });
+    prompt.push_str(&format!(
+        "\n- Summary lines are typically around {} characters long.",
+        conventions.typical_length
+    ));
+    prompt.push_str(match conventions.body_percentage {
+        0..=25 => "\n- Most commits have no body. Leave the description empty unless the change needs explaining.",
+        26..=74 => "\n- Some commits have a body. Add a description only for non-trivial changes.",
+        _ => "\n- Almost every commit has a body describing the change.",
+    });
+
+    if !conventions.examples.is_empty() {
+        prompt.push_str("\n\nRecent commit messages of this repository, as examples of its style:");
+        for example in &conventions.examples {
+            prompt.push_str(&format!("\n\n```\n{}\n```", example));
+        }
+    }
+}
 
-## Critical Rules
+/// Append the scope derived from the changed paths to the user message.
+pub fn append_scope_hint(content: &mut String, scope: Option<&InferredScope>) {
+    if let Some(InferredScope::Configured(scope) | InferredScope::Directory(scope)) = scope {
+        content.push_str(&format!("\n\nSuggested Scope: {}", scope));
+    }
+}
 
-- Always return all three JSON fields in the order: summary, description, warning.
-- Set a field to null when not applicable; never omit fields.
-- Use the user description for context, but do not repeat it verbatim.
-- Only mention ticket numbers in the summary.
-- Be concise and avoid redundant or verbose language.
+/// Build the user message containing the diff and any additional context.
+pub fn build_user_content(
+    template: &Template,
+    diff: &str,
+    privacy: Privacy,
+    user_description: Option<&str>,
+    previous_message: Option<&str>,
+) -> String {
+    let mut context = Context::default();
+    context
+        .set("diff", diff)
+        .set_opt("privacy", privacy.note())
+        .set("user_description", user_description.unwrap_or_default())
+        .set_opt("previous_message", previous_message);
+
+    template.user(&context)
+}
 
-## Output Specification
+/// Build the user message for squashing a series of commits into one.
+pub fn build_squash_content(
+    template: &Template,
+    diff: &str,
+    privacy: Privacy,
+    user_description: Option<&str>,
+    commit_messages: &[String],
+) -> String {
+    let mut context = Context::default();
+    context
+        .set("diff", diff)
+        .set_opt("privacy", privacy.note())
+        .set("user_description", user_description.unwrap_or_default())
+        .set("squashed_messages", message_list(commit_messages));
+
+    template.user(&context)
+}
 
-- The result must be a JSON object with exactly three fields: summary, description, warning (in that order).
-- The description is a single markdown dashed list as a string or null.
-- warning is null, a string, or an array, as noted above.
-- If input is malformed, all fields must be null.
+/// Build the user message for generating a pull request description.
+pub fn build_pr_content(
+    template: &Template,
+    diff: &str,
+    privacy: Privacy,
+    user_description: Option<&str>,
+    branch: Option<&str>,
+    commit_messages: &[String],
+    pr_template: Option<&str>,
+) -> String {
+    let mut context = Context::default();
+    context
+        .set("diff", diff)
+        .set_opt("privacy", privacy.note())
+        .set("user_description", user_description.unwrap_or_default())
+        .set("branch", branch.unwrap_or("(detached HEAD)"))
+        .set("commit_messages", message_list(commit_messages))
+        .set_opt("pr_template", pr_template);
+
+    template.user(&context)
+}
 
-## Output Verbosity
+/// Build the user message for generating changelog entries from a list of commits.
+pub fn build_changelog_content(
+    template: &Template,
+    privacy: Privacy,
+    user_description: Option<&str>,
+    commits: &[String],
+) -> String {
+    let mut context = Context::default();
+    context
+        .set("commits", commits.join("\n\n"))
+        .set_opt("privacy", privacy.note())
+        .set("user_description", user_description.unwrap_or_default());
+
+    template.user(&context)
+}
 
-- Respond in at most 2 short paragraphs for any free-text output outside the JSON fields.
-- For 'description', use at most 5 dashed bullets, each one line.
-- Prioritize complete, actionable answers within these length caps; do not collapse answers prematurely, even if the user input is terse.
-- If you are supplying updates or answering clarifications, keep such updates within 1-2 sentences unless the user explicitly requests a longer explanation."#,
-    )
+/// Render commit messages as a dashed list, indenting their bodies.
+fn message_list(messages: &[String]) -> String {
+    messages
+        .iter()
+        .map(|message| format!("- {}", message.replace('\n', "\n  ")))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
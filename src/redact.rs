@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use log::debug;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::diff::StagedChange;
+use crate::secrets::Finding;
+
+/// Personal data that is always redacted when `pii` is enabled.
+const PII_RULES: [(&str, &str); 3] = [
+    (
+        "email",
+        r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b",
+    ),
+    // Before phone numbers, whose pattern matches the digit groups of an IBAN
+    (
+        "iban",
+        r"\b[A-Z]{2}[0-9]{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b",
+    ),
+    (
+        "phone",
+        r"(?:\+[0-9]{1,3}[ .-]?)?\(?\b[0-9]{2,4}\)?[ .-][0-9]{3,4}[ .-][0-9]{3,4}\b",
+    ),
+];
+
+/// A private key from its header to its footer, across the lines of a diff.
+static PRIVATE_KEY_BLOCK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)(-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----).*?-----END (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----")
+        .expect("Invalid private key pattern")
+});
+
+/// How secrets and personal data are replaced in the text sent to the provider,
+/// from the `[redaction]` section of the repository config.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Whether values found in the added lines are replaced by placeholders.
+    pub enabled: bool,
+    /// Whether email addresses, phone numbers and IBANs are redacted, besides secrets.
+    pub pii: bool,
+    /// Additional patterns to redact, e.g. customer identifiers.
+    pub rules: Vec<RedactionRule>,
+    /// Regexes for values that are never redacted, e.g. `@example\.com$`.
+    pub allowlist: Vec<String>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        RedactionConfig {
+            enabled: true,
+            pii: true,
+            rules: Vec::new(),
+            allowlist: Vec::new(),
+        }
+    }
+}
+
+/// A repository specific redaction pattern. When it contains a capture group, only the
+/// first group is redacted.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionRule {
+    /// The name used in the placeholder, e.g. `customer_id` for `<REDACTED_CUSTOMER_ID_1>`.
+    pub name: String,
+    pub pattern: String,
+}
+
+impl RedactionConfig {
+    /// Check the rule names and that all patterns are valid regexes.
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.rules {
+            if rule.name.is_empty()
+                || !rule
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(format!(
+                    "Invalid redaction rule name '{}', names may only contain letters, digits and underscores",
+                    rule.name
+                ));
+            }
+            compile(&rule.pattern)?;
+        }
+        for pattern in &self.allowlist {
+            compile(pattern)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("Invalid redaction pattern '{}': {}", pattern, e))
+}
+
+/// Replaces secrets and personal data with stable placeholders like `<REDACTED_AWS_KEY_1>`.
+///
+/// Values are collected from the added lines of the changes, then replaced wherever they occur
+/// in the outgoing text, so a value repeated in a context line or the user description is
+/// redacted too. The same value always gets the same placeholder.
+pub struct Redactor {
+    enabled: bool,
+    rules: Vec<(String, Regex)>,
+    allowlist: Vec<Regex>,
+    placeholders: HashMap<String, String>,
+    counts: HashMap<String, usize>,
+}
+
+impl Redactor {
+    pub fn new(config: &RedactionConfig) -> Self {
+        let mut rules = Vec::new();
+        if config.pii {
+            rules.extend(PII_RULES.iter().map(|(name, pattern)| {
+                (
+                    name.to_string(),
+                    Regex::new(pattern).expect("Invalid PII rule"),
+                )
+            }));
+        }
+        // The patterns were checked when the config was loaded
+        rules.extend(
+            config
+                .rules
+                .iter()
+                .filter_map(|rule| Some((rule.name.clone(), compile(&rule.pattern).ok()?))),
+        );
+
+        Redactor {
+            enabled: config.enabled,
+            rules,
+            allowlist: config
+                .allowlist
+                .iter()
+                .filter_map(|pattern| compile(pattern).ok())
+                .collect(),
+            placeholders: HashMap::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Collect the values to redact from the secret findings and the added lines of the changes.
+    pub fn collect(&mut self, change: &StagedChange, findings: &[Finding]) {
+        if !self.enabled {
+            return;
+        }
+
+        for finding in findings {
+            self.add(finding.rule, &finding.secret);
+        }
+
+        let mut matches = Vec::new();
+        for file in change.files.iter().filter(|file| !file.is_binary) {
+            for (_, content) in file.added_lines() {
+                // Each part of the line is redacted by the first rule that matches it
+                let mut spans: Vec<(usize, usize)> = Vec::new();
+                for (name, regex) in &self.rules {
+                    for captures in regex.captures_iter(content) {
+                        let Some(value) = captures.get(1).or_else(|| captures.get(0)) else {
+                            continue;
+                        };
+                        if spans
+                            .iter()
+                            .any(|(start, end)| value.start() < *end && *start < value.end())
+                            || (name == "iban" && !is_valid_iban(value.as_str()))
+                        {
+                            continue;
+                        }
+                        spans.push((value.start(), value.end()));
+                        matches.push((name.clone(), value.as_str().to_string()));
+                    }
+                }
+            }
+        }
+        for (name, value) in matches {
+            self.add(&name, &value);
+        }
+    }
+
+    fn add(&mut self, rule: &str, value: &str) {
+        if value.is_empty()
+            || self.placeholders.contains_key(value)
+            || self.allowlist.iter().any(|regex| regex.is_match(value))
+        {
+            return;
+        }
+
+        let rule = rule.to_uppercase();
+        let count = self.counts.entry(rule.clone()).or_insert(0);
+        *count += 1;
+        let placeholder = format!("<REDACTED_{}_{}>", rule, count);
+        debug!("Redacting {} value as {}", rule.to_lowercase(), placeholder);
+
+        self.placeholders.insert(value.to_string(), placeholder);
+    }
+
+    /// The number of distinct values that are redacted.
+    pub fn len(&self) -> usize {
+        self.placeholders.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.placeholders.is_empty()
+    }
+
+    /// Replace every collected value in the text by its placeholder.
+    pub fn apply(&self, text: &str) -> String {
+        if self.placeholders.is_empty() {
+            return text.to_string();
+        }
+
+        // Longer values first, so a value containing another one is replaced as a whole
+        let mut values = self.placeholders.iter().collect::<Vec<_>>();
+        values.sort_by(|a, b| b.0.len().cmp(&a.0.len()).then(a.0.cmp(b.0)));
+
+        // Only the header of a private key is a finding, but the whole block is secret
+        let mut redacted = PRIVATE_KEY_BLOCK
+            .replace_all(text, |captures: &regex::Captures| {
+                self.placeholders
+                    .get(&captures[1])
+                    .cloned()
+                    .unwrap_or_else(|| captures[0].to_string())
+            })
+            .to_string();
+        for (value, placeholder) in values {
+            redacted = redacted.replace(value.as_str(), placeholder);
+        }
+
+        redacted
+    }
+}
+
+/// Check the ISO 13616 checksum, so arbitrary upper case identifiers are not taken for IBANs.
+fn is_valid_iban(value: &str) -> bool {
+    let compact = value.replace(' ', "");
+    if compact.len() < 15 || compact.len() > 34 {
+        return false;
+    }
+
+    let (head, tail) = compact.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let digits = match c.to_digit(36) {
+            Some(digit) => digit,
+            None => return false,
+        };
+        remainder = match digits {
+            0..=9 => (remainder * 10 + digits) % 97,
+            _ => (remainder * 100 + digits) % 97,
+        };
+    }
+
+    remainder == 1
+}
@@ -0,0 +1,170 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{trace, warn};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::diff::Privacy;
+use crate::git;
+use crate::policy::{Allowlist, PolicyConfig};
+use crate::redact::RedactionConfig;
+use crate::settings;
+
+/// The repository configuration file, checked in at the repository root.
+pub const REPO_CONFIG_FILE: &str = ".ai-commit.toml";
+
+/// The repository prompt file, which takes precedence over the `[prompt]` section.
+pub const PROMPT_FILE: &str = ".ai-commit/prompt.md";
+
+/// The sections of `.ai-commit.toml` that only exist for repositories.
+const REPO_SECTIONS: &[&str] = &["privacy", "prompt", "redaction", "policy"];
+
+/// Settings shared by everyone working on a repository.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RepoConfig {
+    /// The default privacy mode, for repositories whose code must not leave the machine.
+    pub privacy: Option<Privacy>,
+    pub prompt: Option<CustomPrompt>,
+    pub redaction: RedactionConfig,
+    pub policy: PolicyConfig,
+    /// Loaded from the allowlist file, not from the TOML file.
+    #[serde(skip)]
+    pub allowlist: Allowlist,
+    /// Global settings overridden by the repository, such as `model` or `scopes`.
+    #[serde(skip)]
+    pub settings: Map<String, Value>,
+    /// The root of the repository, if there is one.
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+/// Whether the repository prompt extends or replaces the built-in one.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptMode {
+    #[default]
+    Append,
+    Replace,
+}
+
+/// A repository specific system prompt, which may contain `{{variables}}`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CustomPrompt {
+    #[serde(default)]
+    pub mode: PromptMode,
+    pub text: String,
+}
+
+/// Load the configuration of the current repository.
+/// Outside a repository, or without any repository files, the defaults are returned.
+pub fn load() -> Result<RepoConfig, String> {
+    let root = match git::repo_root() {
+        Ok(root) => root,
+        Err(e) => {
+            trace!("Not loading repository config: {}", e);
+            return Ok(RepoConfig::default());
+        }
+    };
+
+    let mut config = match fs::read_to_string(root.join(REPO_CONFIG_FILE)) {
+        Ok(content) => {
+            parse(&content).map_err(|e| format!("Invalid {}: {}", REPO_CONFIG_FILE, e))?
+        }
+        Err(_) => RepoConfig::default(),
+    };
+
+    if let Some(prompt) = load_prompt_file(&root.join(PROMPT_FILE))? {
+        if config.prompt.is_some() {
+            warn!(
+                "Both {} and the [prompt] section of {} exist, using {}",
+                PROMPT_FILE, REPO_CONFIG_FILE, PROMPT_FILE
+            );
+        }
+        config.prompt = Some(prompt);
+    }
+    config.allowlist = Allowlist::load(&root)?;
+    config.root = Some(root);
+    trace!("Repository configuration: {:?}", config);
+
+    Ok(config)
+}
+
+/// Parse the repository file. Global settings are set at the top level next to the
+/// repository's own sections, except those only trusted from the global config.
+fn parse(content: &str) -> Result<RepoConfig, String> {
+    let mut table = toml::from_str::<toml::Table>(content).map_err(|e| e.to_string())?;
+
+    let mut settings = Map::new();
+    let keys = table
+        .keys()
+        .filter(|key| !REPO_SECTIONS.contains(&key.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in keys {
+        let Some(setting) = settings::find(&key) else {
+            continue;
+        };
+        if !setting.repository {
+            return Err(format!(
+                "{} can only be set in the global config, not by a repository",
+                key
+            ));
+        }
+        if let Some(value) = table.remove(&key) {
+            let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+            settings.insert(key, value);
+        }
+    }
+
+    let mut config = toml::Value::Table(table)
+        .try_into::<RepoConfig>()
+        .map_err(|e| e.to_string())?;
+    config.settings = settings;
+
+    Ok(config)
+}
+
+/// Read the prompt file, with an optional front matter block selecting the mode:
+///
+/// ```text
+/// ---
+/// mode: replace
+/// ---
+/// ```
+fn load_prompt_file(path: &Path) -> Result<Option<CustomPrompt>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+
+    let mut mode = PromptMode::default();
+    let mut text = content.as_str();
+    if let Some(rest) = content.strip_prefix("---\n")
+        && let Some((front_matter, body)) = rest.split_once("\n---\n")
+    {
+        for line in front_matter.lines().filter(|line| !line.trim().is_empty()) {
+            match line
+                .split_once(':')
+                .map(|(key, value)| (key.trim(), value.trim()))
+            {
+                Some(("mode", "append")) => mode = PromptMode::Append,
+                Some(("mode", "replace")) => mode = PromptMode::Replace,
+                _ => {
+                    return Err(format!(
+                        "Invalid front matter in {}: '{}', expected 'mode: append' or 'mode: replace'",
+                        PROMPT_FILE, line
+                    ));
+                }
+            }
+        }
+        text = body;
+    }
+
+    Ok(Some(CustomPrompt {
+        mode,
+        text: text.trim().to_string(),
+    }))
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use dialoguer::console::Term;
+use log::{error, info};
+
+use crate::api::provider::{Provider, ToolSpec};
+use crate::cli::{self, RewordChoice};
+use crate::commit_editor::edit_message;
+use crate::config_manager::AppConfig;
+use crate::diff::{DiffSource, StagedChange};
+use crate::git::{self, HistoryCommit};
+use crate::history;
+use crate::payload::{self, Outcome, Payload};
+use crate::policy;
+use crate::prompt::{self, PromptVariables};
+use crate::style::InferredScope;
+use crate::ticket;
+use crate::trailers;
+use crate::warnings;
+
+/// A commit of the range with changes to describe.
+struct RewordRequest<'a> {
+    commit: &'a HistoryCommit,
+    /// The changes of the commit, to check the warnings against.
+    diff: StagedChange,
+    scope: Option<InferredScope>,
+}
+
+/// Generate new messages for the commits in a range, review them and rewrite the history.
+pub fn run(config: &AppConfig, range: &str) -> Result<(), Box<dyn Error>> {
+    let terminal = Term::stdout();
+
+    let commits = git::list_commits(range)?;
+    if commits.is_empty() {
+        terminal.write_line(&format!("No commits found in range {}", range))?;
+        return Ok(());
+    }
+
+    // Only linear history can be replayed, so refuse merges before anything is sent
+    if let Some(merge) = commits.iter().find(|commit| commit.is_merge) {
+        return Err(format!("Cannot reword across merge commit {}", merge.id).into());
+    }
+
+    // Build the prompt for every commit up front, so the total cost is known before proceeding
+    let style = config.message_style();
+    // Learn from the history below the range, not from the messages being replaced
+    let conventions = history::learn(&format!("{}^", commits[0].id), config.history_commits);
+    let ticket = ticket::detect(&config.ticket, config.get_user_desc());
+    let trailers = trailers::collect(config, &style, ticket.as_deref())?;
+    let template = config.template("review");
+    let mut requests: Vec<RewordRequest> = Vec::new();
+
+    for commit in &commits {
+        let diff = git::get_diff(
+            &DiffSource::Commit(commit.id.to_string()),
+            config.diff_settings(),
+        )?;
+        if diff.is_empty() {
+            info!("Skipping {} as it has no changes to describe", commit.id);
+            continue;
+        }
+
+        let scope = style.scope_for(&diff, &config.scopes);
+        requests.push(RewordRequest {
+            commit,
+            diff,
+            scope,
+        });
+    }
+
+    if requests.is_empty() {
+        terminal.write_line("None of the commits contain changes to describe")?;
+        return Ok(());
+    }
+
+    // Look for secrets before anything leaves the machine. The placeholders replacing them are
+    // stable across all commits
+    let Some((_, redactor)) = payload::screen(
+        config,
+        requests.iter().map(|request| &request.diff),
+        config.privacy,
+    ) else {
+        return Ok(());
+    };
+
+    let mut payloads = Vec::new();
+    for RewordRequest {
+        commit,
+        diff,
+        scope,
+    } in &requests
+    {
+        let variables = PromptVariables::new(diff, config.get_user_desc(), ticket.as_deref());
+        let mut system_prompt =
+            prompt::get_system_prompt(&template, &style, config.prompt.as_ref(), &variables);
+        if let Some(conventions) = &conventions {
+            prompt::append_conventions(&mut system_prompt, conventions);
+        }
+        let mut content = prompt::build_user_content(
+            &template,
+            &diff.render(config.privacy),
+            config.privacy,
+            config.get_user_desc(),
+            Some(&commit.message),
+        );
+        prompt::append_scope_hint(&mut content, scope.as_ref());
+
+        payloads.push(Payload {
+            label: Some(format!("{} {}", commit.short_id(), commit.summary())),
+            system_prompt,
+            content,
+            diff,
+        });
+    }
+
+    let outcome = payload::prepare(
+        config,
+        &redactor,
+        &ToolSpec::commit_message(&style),
+        &format!("Generate new messages for {} commits?", requests.len()),
+        &mut payloads,
+    )?;
+    if outcome != Outcome::Send {
+        return Ok(());
+    }
+
+    // Save the current tip before anything is generated, so the rewrite can be rolled back
+    let backup_ref = git::backup_head("reword")?;
+
+    let (provider, key) = config.get_provider_key();
+    let api = Provider::create_provider(provider, key, config.base_url.as_deref())?;
+    let width = terminal.size().1 as usize;
+
+    // Generate and review the new messages one commit at a time
+    let mut messages = HashMap::new();
+    for (
+        RewordRequest {
+            commit,
+            diff,
+            scope,
+        },
+        Payload {
+            system_prompt,
+            content,
+            ..
+        },
+    ) in requests.iter().zip(&payloads)
+    {
+        terminal.write_line(&format!(
+            "\nGenerating message for {} {}...",
+            commit.short_id(),
+            commit.summary()
+        ))?;
+
+        let generated =
+            match api.generate_commit_message(config.get_model(), &style, system_prompt, content) {
+                Ok(generated) => generated,
+                Err(e) => {
+                    error!("Failed to generate a message for {}: {}", commit.id, e);
+                    continue;
+                }
+            };
+        let mut new_message = style.format_message(&generated, scope.as_ref());
+        if let Some(reference) = &ticket {
+            new_message = ticket::apply(
+                &new_message,
+                &style,
+                reference,
+                config.ticket.placement,
+                conventions
+                    .as_ref()
+                    .and_then(|c| c.ticket_prefix.as_deref()),
+            );
+        }
+        new_message = trailers::append(&new_message, &trailers);
+
+        terminal.write_line(&cli::side_by_side(
+            ("Original", &commit.message),
+            ("Generated", &new_message),
+            width,
+        ))?;
+        let warnings = policy::filter_allowed(
+            warnings::verify(generated.warnings, diff),
+            diff,
+            &config.allowlist,
+        );
+        warnings::print(&terminal, &warnings)?;
+
+        match cli::prompt_reword_action() {
+            RewordChoice::Accept => {
+                messages.insert(commit.id, new_message);
+            }
+            RewordChoice::Edit => {
+                edit_message(&mut new_message)?;
+                if !new_message.trim().is_empty() {
+                    messages.insert(commit.id, new_message);
+                }
+            }
+            RewordChoice::Keep => {}
+        }
+    }
+
+    if messages.is_empty() {
+        terminal.write_line("No commits were reworded")?;
+        return Ok(());
+    }
+
+    if !cli::prompt_for_confirmation(&format!("Rewrite {} commits?", messages.len())) {
+        terminal.write_line("Operation canceled by the user")?;
+        return Ok(());
+    }
+
+    let new_head = git::reword_commits(&messages, &backup_ref)?;
+
+    terminal.write_line(&format!(
+        "Reworded {} commits, new HEAD is {}",
+        messages.len(),
+        new_head
+    ))?;
+    terminal.write_line(&format!(
+        "The original history is saved as {}. To roll back, run: git reset --hard {}",
+        backup_ref, backup_ref
+    ))?;
+
+    Ok(())
+}
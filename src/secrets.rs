@@ -0,0 +1,238 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use dialoguer::console::{Style, Term};
+use log::debug;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::cli;
+use crate::diff::StagedChange;
+use crate::policy::Allowlist;
+
+/// Strings shorter than this are never reported by the entropy check.
+const MIN_ENTROPY_LENGTH: usize = 20;
+
+/// Shannon entropy in bits per character above which a string looks random.
+/// Hex digests stay below it, base64 encoded keys usually exceed it.
+const ENTROPY_THRESHOLD: f64 = 4.0;
+
+/// Files full of checksums, which the entropy check would flag on every line.
+const CHECKSUM_FILES: [&str; 6] = [
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "go.sum",
+    "poetry.lock",
+];
+
+/// What happens when the local scan finds possible secrets in the changes.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretScan {
+    /// Do not scan.
+    Off,
+    /// Report the findings and ask before sending the changes.
+    #[default]
+    Confirm,
+    /// Report the findings and refuse to send the changes.
+    Block,
+}
+
+/// A pattern for a well-known kind of secret.
+struct Rule {
+    id: &'static str,
+    description: &'static str,
+    pattern: &'static str,
+}
+
+const RULES: [Rule; 8] = [
+    Rule {
+        id: "aws_key",
+        description: "AWS access key",
+        pattern: r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+    },
+    Rule {
+        id: "aws_secret",
+        description: "AWS secret access key",
+        pattern: r#"(?i)aws_?secret_?(?:access_?)?key["']?\s*[:=]\s*["']?([A-Za-z0-9/+=]{40})"#,
+    },
+    Rule {
+        id: "github_token",
+        description: "GitHub token",
+        pattern: r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})\b",
+    },
+    Rule {
+        id: "slack_token",
+        description: "Slack token",
+        pattern: r"\bxox[abposr]-[A-Za-z0-9-]{10,}\b",
+    },
+    Rule {
+        id: "private_key",
+        description: "private key",
+        pattern: r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----",
+    },
+    Rule {
+        id: "jwt",
+        description: "JSON Web Token",
+        pattern: r"\beyJ[A-Za-z0-9_-]{10,}\.eyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+    },
+    Rule {
+        id: "connection_string",
+        description: "connection string with password",
+        pattern: r"\b[a-z][a-z0-9+.-]*://[^\s:@/]+:([^\s@/]+)@[^\s/]+",
+    },
+    Rule {
+        id: "password",
+        description: "hardcoded password",
+        pattern: r#"(?i)\b(?:password|passwd|pwd|secret)["']?\s*[:=]\s*["']([^"'\s]{8,})["']"#,
+    },
+];
+
+/// A possible secret in an added line.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub file: PathBuf,
+    pub line: u32,
+    /// The identifier of the rule that matched, e.g. `aws_key`.
+    pub rule: &'static str,
+    pub description: &'static str,
+    /// The matched secret.
+    pub secret: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} {} [{}]",
+            self.file.display(),
+            self.line,
+            self.description,
+            mask(&self.secret),
+            self.rule
+        )
+    }
+}
+
+/// Scan the added lines of the changes for secrets, skipping allowlisted files and lines.
+pub fn scan(change: &StagedChange, allowlist: &Allowlist) -> Vec<Finding> {
+    let rules = RULES
+        .iter()
+        .map(|rule| (rule, Regex::new(rule.pattern).expect("Invalid secret rule")))
+        .collect::<Vec<_>>();
+    let candidate = Regex::new(r#"(?:[:=]\s*["'`]?|["'`])([A-Za-z0-9+/_\-]{20,}={0,2})"#)
+        .expect("Invalid entropy pattern");
+
+    let mut findings = Vec::new();
+    for file in change.files.iter().filter(|file| !file.is_binary) {
+        let checks_entropy = !is_checksum_file(file.path());
+
+        for (line, content) in file.added_lines() {
+            if allowlist.allows(file.path(), Some(content)) {
+                continue;
+            }
+            let before = findings.len();
+
+            for (rule, regex) in &rules {
+                for captures in regex.captures_iter(content) {
+                    let secret = captures.get(1).or_else(|| captures.get(0));
+                    findings.push(Finding {
+                        file: file.path().to_path_buf(),
+                        line,
+                        rule: rule.id,
+                        description: rule.description,
+                        secret: secret.map(|m| m.as_str().to_string()).unwrap_or_default(),
+                    });
+                }
+            }
+
+            // Only look for random-looking strings if no specific rule matched the line
+            if checks_entropy && findings.len() == before {
+                for captures in candidate.captures_iter(content) {
+                    let token = &captures[1];
+                    if token.len() >= MIN_ENTROPY_LENGTH
+                        && token.chars().any(|c| c.is_ascii_digit())
+                        && token.chars().any(|c| c.is_ascii_alphabetic())
+                        && entropy(token) > ENTROPY_THRESHOLD
+                    {
+                        findings.push(Finding {
+                            file: file.path().to_path_buf(),
+                            line,
+                            rule: "high_entropy",
+                            description: "high-entropy string",
+                            secret: token.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    debug!("Secret scan found {} possible secrets", findings.len());
+
+    findings
+}
+
+/// Report the findings and decide whether the changes may be sent to the provider.
+/// Returns `true` when there is nothing to report or the user confirmed sending anyway.
+pub fn review(findings: &[Finding], mode: SecretScan) -> bool {
+    if findings.is_empty() || mode == SecretScan::Off {
+        return true;
+    }
+
+    let terminal = Term::stdout();
+    let warning_style = Style::new().red().bold();
+    let _ = terminal.write_line(
+        &warning_style
+            .apply_to("Possible secrets found in the changes:")
+            .to_string(),
+    );
+    for finding in findings {
+        let _ = terminal.write_line(&format!("  {}", finding));
+    }
+
+    match mode {
+        SecretScan::Block => {
+            let _ = terminal.write_line(
+                "Nothing was sent. Remove the secrets from the changes, or set secret_scan to \"confirm\" to review them.",
+            );
+            false
+        }
+        _ => cli::prompt_for_confirmation("Send the changes to the provider anyway?"),
+    }
+}
+
+/// Shannon entropy of the string in bits per character.
+fn entropy(text: &str) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in text.bytes() {
+        counts[byte as usize] += 1;
+    }
+
+    let length = text.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn is_checksum_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| CHECKSUM_FILES.contains(&name))
+}
+
+/// Show only the first characters of a secret.
+fn mask(secret: &str) -> String {
+    let visible = secret.chars().take(4).collect::<String>();
+    format!(
+        "{}{}",
+        visible,
+        "*".repeat(secret.chars().count().saturating_sub(4).min(12))
+    )
+}
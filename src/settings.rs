@@ -0,0 +1,182 @@
+use serde_json::Value;
+
+/// The type of a setting, used to parse values given as text on the command line or in the
+/// environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    String,
+    /// A whole number, such as `context_lines`.
+    Integer,
+    /// A number that may have a fraction, such as `max_cost`.
+    Number,
+    /// One of a fixed set of names.
+    Enum(&'static [&'static str]),
+    /// Names mapped to strings, set entry by entry, e.g. `scopes.web`.
+    Map,
+    /// A section with its own fields, such as `ticket`, given as JSON.
+    Table,
+}
+
+/// A setting of the global config.
+pub struct Setting {
+    pub key: &'static str,
+    pub kind: Kind,
+    /// Secret values are never shown.
+    pub secret: bool,
+    /// Whether `.ai-commit.toml` may set it. Settings that decide which key is used, where it
+    /// is sent or which files are read, and the secret scan and cost limits that protect you,
+    /// are only trusted from your own config, not from a repository you cloned.
+    pub repository: bool,
+    /// The smallest and largest allowed value of numbers.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+const fn setting(key: &'static str, kind: Kind) -> Setting {
+    Setting {
+        key,
+        kind,
+        secret: false,
+        repository: true,
+        min: None,
+        max: None,
+    }
+}
+
+const fn user_only(key: &'static str, kind: Kind) -> Setting {
+    Setting {
+        key,
+        kind,
+        secret: false,
+        repository: false,
+        min: None,
+        max: None,
+    }
+}
+
+/// Every setting, in the order they are listed.
+pub const SETTINGS: &[Setting] = &[
+    setting("api_provider", Kind::Enum(&["openai", "anthropic"])),
+    setting("model", Kind::String),
+    setting("style", Kind::String),
+    setting("profile", Kind::String),
+    user_only("credential", Kind::String),
+    setting("context_lines", Kind::Integer).between(0.0, 1000.0),
+    setting("rename_threshold", Kind::Integer).between(0.0, 100.0),
+    setting("copy_threshold", Kind::Integer).between(0.0, 100.0),
+    setting("history_commits", Kind::Integer).between(0.0, 1000.0),
+    setting("scopes", Kind::Map),
+    setting("ticket", Kind::Table),
+    setting("trailers", Kind::Map),
+    setting("roster", Kind::Map),
+    user_only("templates", Kind::Map),
+    user_only("secret_scan", Kind::Enum(&["confirm", "block", "off"])),
+    Setting {
+        key: "api_keys",
+        kind: Kind::Map,
+        secret: true,
+        repository: false,
+        min: None,
+        max: None,
+    },
+    user_only("key_command", Kind::String),
+    user_only("key_storage", Kind::Enum(&["encrypted", "plaintext"])),
+    user_only("base_url", Kind::String),
+    user_only("max_cost", Kind::Number).at_least(0.0),
+    user_only("max_prompt_tokens", Kind::Integer).at_least(1.0),
+    user_only("profiles", Kind::Table),
+];
+
+/// Find a setting by its key.
+pub fn find(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.key == key)
+}
+
+impl Setting {
+    const fn between(self, min: f64, max: f64) -> Setting {
+        Setting {
+            min: Some(min),
+            max: Some(max),
+            ..self
+        }
+    }
+
+    const fn at_least(self, min: f64) -> Setting {
+        Setting {
+            min: Some(min),
+            ..self
+        }
+    }
+
+    /// Check that a number is within the allowed range.
+    pub fn check(&self, value: &Value) -> Result<(), String> {
+        let Some(number) = value.as_f64() else {
+            return Ok(());
+        };
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if number < min || number > max => Err(format!(
+                "must be between {} and {}, got {}",
+                min, max, value
+            )),
+            (Some(min), None) if number < min => {
+                Err(format!("must be at least {}, got {}", min, value))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The environment variable overriding the setting, e.g. `AI_COMMIT_MODEL`. Maps and tables
+    /// cannot be set from the environment.
+    pub fn env_var(&self) -> Option<String> {
+        match self.kind {
+            Kind::Map | Kind::Table => None,
+            _ => Some(format!("AI_COMMIT_{}", self.key.to_uppercase())),
+        }
+    }
+
+    /// Parse a value given as text.
+    pub fn parse(&self, text: &str) -> Result<Value, String> {
+        let value = self.parse_kind(text.trim())?;
+        self.check(&value)?;
+
+        Ok(value)
+    }
+
+    fn parse_kind(&self, text: &str) -> Result<Value, String> {
+        match self.kind {
+            Kind::String => Ok(Value::String(text.to_string())),
+            Kind::Integer => text
+                .parse::<u64>()
+                .map(Value::from)
+                .map_err(|_| format!("expected a whole number, got '{}'", text)),
+            Kind::Number => text
+                .parse::<f64>()
+                .ok()
+                .and_then(serde_json::Number::from_f64)
+                .map(Value::Number)
+                .ok_or_else(|| format!("expected a number, got '{}'", text)),
+            Kind::Enum(names) => match names.contains(&text) {
+                true => Ok(Value::String(text.to_string())),
+                false => Err(format!(
+                    "expected one of {}, got '{}'",
+                    names.join(", "),
+                    text
+                )),
+            },
+            Kind::Map | Kind::Table => match serde_json::from_str(text) {
+                Ok(Value::Object(map)) => Ok(Value::Object(map)),
+                _ => Err(format!("expected a JSON object, got '{}'", text)),
+            },
+        }
+    }
+
+    /// Parse the value of an entry, e.g. `web` of `scopes.web`. Map entries are strings, the
+    /// fields of a table are read as JSON when they are valid JSON.
+    pub fn parse_entry(&self, text: &str) -> Value {
+        match self.kind {
+            Kind::Table => serde_json::from_str(text.trim())
+                .unwrap_or_else(|_| Value::String(text.trim().to_string())),
+            _ => Value::String(text.trim().to_string()),
+        }
+    }
+}
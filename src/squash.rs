@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::process;
+
+use dialoguer::console::Term;
+use log::{error, info, trace};
+
+use crate::api::provider::{Provider, ToolSpec};
+use crate::cli::{self, UserChoice};
+use crate::commit_editor::edit_message;
+use crate::config_manager::AppConfig;
+use crate::diff::DiffSource;
+use crate::git;
+use crate::history;
+use crate::payload::{self, Outcome, Payload};
+use crate::policy;
+use crate::prompt::{self, PromptVariables};
+use crate::secrets::SecretScan;
+use crate::ticket;
+use crate::trailers;
+use crate::warnings;
+
+/// Generate a single message for all commits since `base`, optionally squashing them.
+pub fn run(config: &AppConfig, base: &str, commit: bool) -> Result<(), Box<dyn Error>> {
+    let terminal = Term::stdout();
+
+    let commits = git::list_commits(&format!("{}..HEAD", base))?;
+    if commits.is_empty() {
+        terminal.write_line(&format!("No commits found since {}", base))?;
+        return Ok(());
+    }
+
+    // The combined change of the branch, relative to where it diverged from the base
+    let diff = git::get_diff(
+        &DiffSource::Range(format!("{}...HEAD", base)),
+        config.diff_settings(),
+    )?;
+    if diff.is_empty() {
+        terminal.write_line(&format!("No changes found since {}", base))?;
+        return Ok(());
+    }
+
+    // Look for secrets before anything leaves the machine
+    let Some((findings, redactor)) = payload::screen(config, [&diff], config.privacy) else {
+        return Ok(());
+    };
+
+    let patch = diff.render(config.privacy);
+    trace!("Squashed diff: \n{}", &patch);
+
+    let messages = commits
+        .iter()
+        .map(|commit| commit.message.clone())
+        .collect::<Vec<_>>();
+    let style = config.message_style();
+    let scope = style.scope_for(&diff, &config.scopes);
+    let ticket = ticket::detect(&config.ticket, config.get_user_desc());
+    let variables = PromptVariables::new(&diff, config.get_user_desc(), ticket.as_deref());
+    let trailers = trailers::collect(config, &style, ticket.as_deref())?;
+    let template = config.template("commit");
+    let mut system_prompt =
+        prompt::get_system_prompt(&template, &style, config.prompt.as_ref(), &variables);
+    let conventions = history::learn(base, config.history_commits);
+    if let Some(conventions) = &conventions {
+        prompt::append_conventions(&mut system_prompt, conventions);
+    }
+    let mut content = prompt::build_squash_content(
+        &template,
+        &patch,
+        config.privacy,
+        config.get_user_desc(),
+        &messages,
+    );
+    prompt::append_scope_hint(&mut content, scope.as_ref());
+
+    let mut payloads = [Payload {
+        label: None,
+        system_prompt,
+        content,
+        diff: &diff,
+    }];
+    let outcome = payload::prepare(
+        config,
+        &redactor,
+        &ToolSpec::commit_message(&style),
+        &format!("Generate a squash message for {} commits?", commits.len()),
+        &mut payloads,
+    )?;
+    if outcome != Outcome::Send {
+        return Ok(());
+    }
+    let [payload] = payloads;
+
+    // Save the current tip before anything is generated, so the squash can be rolled back
+    let backup_ref = match commit {
+        true => Some(git::backup_head("squash")?),
+        false => None,
+    };
+
+    let (provider, key) = config.get_provider_key();
+    let api = Provider::create_provider(provider, key, config.base_url.as_deref())?;
+
+    terminal.write_line("Generating squash message...")?;
+
+    let generated = api.generate_commit_message(
+        config.get_model(),
+        &style,
+        &payload.system_prompt,
+        &payload.content,
+    )?;
+    let mut commit_message = style.format_message(&generated, scope.as_ref());
+    if let Some(reference) = &ticket {
+        commit_message = ticket::apply(
+            &commit_message,
+            &style,
+            reference,
+            config.ticket.placement,
+            conventions
+                .as_ref()
+                .and_then(|c| c.ticket_prefix.as_deref()),
+        );
+    }
+    commit_message = trailers::append(&commit_message, &trailers);
+
+    terminal.write_line(&commit_message)?;
+
+    let warnings = policy::filter_allowed(
+        warnings::verify(generated.warnings, &diff),
+        &diff,
+        &config.allowlist,
+    );
+    warnings::print(&terminal, &warnings)?;
+
+    let Some(backup_ref) = backup_ref else {
+        return Ok(());
+    };
+
+    match cli::prompt_user_for_action() {
+        UserChoice::Edit => {
+            info!("User chose to edit the squash message.");
+            edit_message(&mut commit_message)?;
+        }
+        UserChoice::Commit => info!("User accepted the squash message."),
+        UserChoice::Cancel => {
+            info!("User canceled the squash.");
+            return Ok(());
+        }
+    }
+
+    // Enforce the repository policy on the warnings and the local findings
+    let findings = match config.secret_scan {
+        SecretScan::Off => Vec::new(),
+        _ => findings,
+    };
+    if !policy::enforce(config.warning_policy, &policy::merge(warnings, &findings)) {
+        terminal.write_line("Nothing was squashed")?;
+        process::exit(1);
+    }
+
+    match git::squash_onto(base, &commit_message, &backup_ref) {
+        Ok(new_head) => {
+            terminal.write_line(&format!(
+                "Squashed {} commits into {}",
+                commits.len(),
+                new_head
+            ))?;
+            terminal.write_line(&format!(
+                "The original history is saved as {}. To roll back, run: git reset --hard {}",
+                backup_ref, backup_ref
+            ))?;
+        }
+        Err(e) => error!("Failed to squash commits: {}", e),
+    }
+
+    Ok(())
+}
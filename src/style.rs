@@ -0,0 +1,552 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::api::provider::CommitMessage;
+use crate::diff::StagedChange;
+use crate::trailers::{self, Trailer};
+
+/// Commit types accepted by the Conventional Commits style.
+const CONVENTIONAL_TYPES: [&str; 11] = [
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// Commit types accepted by the Angular commit message guidelines.
+const ANGULAR_TYPES: [&str; 8] = [
+    "build", "ci", "docs", "feat", "fix", "perf", "refactor", "test",
+];
+
+/// The most common gitmoji shortcodes and their meaning.
+const GITMOJIS: [(&str, &str); 20] = [
+    (":sparkles:", "introduce new features"),
+    (":bug:", "fix a bug"),
+    (":ambulance:", "critical hotfix"),
+    (":memo:", "add or update documentation"),
+    (":recycle:", "refactor code"),
+    (":zap:", "improve performance"),
+    (":white_check_mark:", "add, update or pass tests"),
+    (":art:", "improve structure or format of the code"),
+    (":fire:", "remove code or files"),
+    (":lipstick:", "add or update the UI and style files"),
+    (":lock:", "fix security or privacy issues"),
+    (":wrench:", "add or update configuration files"),
+    (":arrow_up:", "upgrade dependencies"),
+    (":arrow_down:", "downgrade dependencies"),
+    (":construction_worker:", "add or update CI build system"),
+    (":rocket:", "deploy stuff"),
+    (":boom:", "introduce breaking changes"),
+    (":truck:", "move or rename resources"),
+    (":label:", "add or update types"),
+    (":card_file_box:", "perform database related changes"),
+];
+
+/// Top-level directories that group code rather than name a component, skipped when
+/// guessing a scope from the changed paths.
+const CONTAINER_DIRS: [&str; 8] = [
+    "src", "lib", "packages", "apps", "crates", "services", "modules", "libs",
+];
+
+/// Names of the built-in styles.
+pub const BUILT_IN_STYLES: [&str; 5] = ["plain", "conventional", "gitmoji", "kernel", "angular"];
+
+/// How the first letter of the subject is cased.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubjectCase {
+    #[default]
+    Unchanged,
+    Lower,
+    Upper,
+}
+
+/// How generated commit messages are worded and formatted.
+///
+/// Built-in styles are defined below; user-defined styles are read from
+/// `styles/<name>.json` in the config directory, with every field optional.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageStyle {
+    #[serde(skip)]
+    pub name: String,
+    /// The summary line, with the placeholders `{type}`, `{scope}`, `{breaking}`, `{emoji}`
+    /// and `{subject}`. Text in square brackets is dropped when a placeholder in it is empty;
+    /// `[[` and `]]` stand for literal brackets.
+    pub summary_pattern: String,
+    /// Additional instructions for the model, appended to the system prompt.
+    pub instructions: Option<String>,
+    /// The allowed values of `{type}`.
+    pub types: Vec<String>,
+    /// The allowed values of `{emoji}` and their meaning.
+    pub emojis: BTreeMap<String, String>,
+    pub subject_case: SubjectCase,
+    /// The maximum length of the whole summary line.
+    pub max_summary_length: Option<usize>,
+    /// The column at which description lines are wrapped.
+    pub wrap_body: Option<usize>,
+    /// The trailer token used to describe breaking changes, e.g. `BREAKING CHANGE`.
+    pub breaking_trailer: Option<String>,
+    /// Whether a `Signed-off-by` trailer with the committer's identity is always added, like `--signoff`.
+    pub signoff: bool,
+}
+
+/// A scope derived from the changed paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InferredScope {
+    /// Every changed file matched the same entry of the configured path-to-scope map.
+    Configured(String),
+    /// Every changed file lives under the same directory, which is only a suggestion.
+    Directory(String),
+}
+
+impl Default for MessageStyle {
+    fn default() -> Self {
+        MessageStyle {
+            name: "custom".to_string(),
+            summary_pattern: "{subject}".to_string(),
+            instructions: None,
+            types: CONVENTIONAL_TYPES.iter().map(|t| t.to_string()).collect(),
+            emojis: GITMOJIS
+                .iter()
+                .map(|(code, meaning)| (code.to_string(), meaning.to_string()))
+                .collect(),
+            subject_case: SubjectCase::Unchanged,
+            max_summary_length: None,
+            wrap_body: None,
+            breaking_trailer: None,
+            signoff: false,
+        }
+    }
+}
+
+impl MessageStyle {
+    /// Load a built-in style, or a user-defined one from `styles/<name>.json` in the config directory.
+    pub fn load(name: &str, config_dir: Option<&Path>) -> Result<Self, String> {
+        if let Some(style) = Self::built_in(name) {
+            return Ok(style);
+        }
+
+        let path = config_dir
+            .map(|dir| dir.join("styles").join(format!("{}.json", name)))
+            .filter(|path| path.exists())
+            .ok_or_else(|| {
+                format!(
+                    "Unknown style '{}', expected one of {} or a file styles/{}.json in the config directory",
+                    name,
+                    BUILT_IN_STYLES.join(", "),
+                    name
+                )
+            })?;
+
+        debug!("Loading style from {}", path.display());
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut style = serde_json::from_str::<MessageStyle>(&content)
+            .map_err(|e| format!("Invalid style file {}: {}", path.display(), e))?;
+        style.name = name.to_string();
+
+        Ok(style)
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        let style = match name {
+            "plain" => MessageStyle {
+                subject_case: SubjectCase::Upper,
+                ..MessageStyle::default()
+            },
+            "conventional" => MessageStyle {
+                summary_pattern: "{type}[({scope})]{breaking}: {subject}".to_string(),
+                subject_case: SubjectCase::Lower,
+                max_summary_length: Some(72),
+                breaking_trailer: Some("BREAKING CHANGE".to_string()),
+                instructions: Some(
+                    "The repository follows the Conventional Commits specification. Use `feat` only for user-facing features and `fix` only for bug fixes.".to_string(),
+                ),
+                ..MessageStyle::default()
+            },
+            "gitmoji" => MessageStyle {
+                summary_pattern: "{emoji} {subject}".to_string(),
+                subject_case: SubjectCase::Upper,
+                max_summary_length: Some(72),
+                instructions: Some(
+                    "The repository follows gitmoji. Pick the single emoji that best describes the intent of the change.".to_string(),
+                ),
+                ..MessageStyle::default()
+            },
+            "kernel" => MessageStyle {
+                summary_pattern: "[{scope}: ]{subject}".to_string(),
+                subject_case: SubjectCase::Lower,
+                max_summary_length: Some(75),
+                wrap_body: Some(75),
+                signoff: true,
+                instructions: Some(
+                    "The repository follows the Linux kernel conventions. The scope is the subsystem, e.g. `net`, `mm` or `drm/i915`. Write the description as plain prose paragraphs, not a dashed list: explain the problem first, then how the change solves it.".to_string(),
+                ),
+                ..MessageStyle::default()
+            },
+            "angular" => MessageStyle {
+                summary_pattern: "{type}[({scope})]: {subject}".to_string(),
+                types: ANGULAR_TYPES.iter().map(|t| t.to_string()).collect(),
+                subject_case: SubjectCase::Lower,
+                max_summary_length: Some(100),
+                wrap_body: Some(100),
+                breaking_trailer: Some("BREAKING CHANGE".to_string()),
+                instructions: Some(
+                    "The repository follows the Angular commit message guidelines. Do not end the subject with a period.".to_string(),
+                ),
+                ..MessageStyle::default()
+            },
+            _ => return None,
+        };
+
+        Some(MessageStyle {
+            name: name.to_string(),
+            ..style
+        })
+    }
+
+    pub fn uses_type(&self) -> bool {
+        self.summary_pattern.contains("{type}")
+    }
+
+    pub fn uses_scope(&self) -> bool {
+        self.summary_pattern.contains("{scope}")
+    }
+
+    pub fn uses_emoji(&self) -> bool {
+        self.summary_pattern.contains("{emoji}")
+    }
+
+    /// Whether the model is asked to describe breaking changes.
+    pub fn uses_breaking(&self) -> bool {
+        self.summary_pattern.contains("{breaking}") || self.breaking_trailer.is_some()
+    }
+
+    /// System prompt instructions describing this style.
+    /// The plain style keeps the default wording and adds nothing.
+    pub fn prompt_instructions(&self) -> Option<String> {
+        if self.name == "plain" {
+            return None;
+        }
+
+        let mut instructions = format!(
+            "## Message Style\n\nThe summary line is rendered as `{}`. The `summary` field is only the `{{subject}}` part; never repeat the other parts in it.",
+            self.summary_pattern
+        );
+        if self.uses_type() {
+            instructions.push_str(&format!("\n- `type`: one of {}", self.types.join(", ")));
+        }
+        if self.uses_scope() {
+            instructions.push_str(
+                "\n- `scope`: a short lowercase noun naming the affected component, or null. Prefer the suggested scope when one is provided.",
+            );
+        }
+        if self.uses_emoji() {
+            let emojis = self
+                .emojis
+                .iter()
+                .map(|(code, meaning)| format!("{} ({})", code, meaning))
+                .collect::<Vec<_>>()
+                .join(", ");
+            instructions.push_str(&format!("\n- `emoji`: one of {}", emojis));
+        }
+        if self.uses_breaking() {
+            instructions.push_str(
+                "\n- `breaking`: a one-sentence description of what users have to change, or null if the change is backwards compatible",
+            );
+        }
+
+        match self.subject_case {
+            SubjectCase::Lower => instructions.push_str(
+                "\n\nWrite the subject in the imperative mood, starting with a lowercase letter, without a trailing period.",
+            ),
+            SubjectCase::Upper => instructions.push_str(
+                "\n\nWrite the subject in the imperative mood, starting with a capital letter, without a trailing period.",
+            ),
+            SubjectCase::Unchanged => {}
+        }
+        if let Some(max) = self.max_summary_length {
+            instructions.push_str(&format!(
+                " The whole summary line must stay under {} characters.",
+                max
+            ));
+        }
+        if let Some(extra) = &self.instructions {
+            instructions.push_str(&format!("\n\n{}", extra));
+        }
+
+        Some(instructions)
+    }
+
+    /// Derive the scope of the change from its paths, for styles that use scopes.
+    pub fn scope_for(
+        &self,
+        change: &StagedChange,
+        scopes: &BTreeMap<String, String>,
+    ) -> Option<InferredScope> {
+        self.uses_scope()
+            .then(|| infer_scope(change, scopes))
+            .flatten()
+    }
+
+    /// Format the generated message according to this style.
+    /// A configured scope takes precedence over the one chosen by the model.
+    pub fn format_message(&self, message: &CommitMessage, scope: Option<&InferredScope>) -> String {
+        let commit_type = message
+            .commit_type
+            .as_deref()
+            .and_then(|commit_type| {
+                self.types
+                    .iter()
+                    .find(|allowed| allowed.eq_ignore_ascii_case(commit_type.trim()))
+                    .cloned()
+            })
+            .or_else(|| self.uses_type().then(|| fallback_type(&self.types)))
+            .unwrap_or_default();
+        let scope = match scope {
+            Some(InferredScope::Configured(scope)) => Some(scope.as_str()),
+            Some(InferredScope::Directory(scope)) => message.scope.as_deref().or(Some(scope)),
+            None => message.scope.as_deref(),
+        }
+        .map(str::trim)
+        .unwrap_or_default();
+        let emoji = message
+            .emoji
+            .as_deref()
+            .filter(|emoji| self.emojis.contains_key(*emoji))
+            .unwrap_or_default();
+        // Models often answer an empty string for changes that are not breaking
+        let breaking_change = message
+            .breaking
+            .as_deref()
+            .map(str::trim)
+            .filter(|breaking| !breaking.is_empty());
+        let breaking = match breaking_change {
+            Some(_) => "!",
+            None => "",
+        };
+        let subject = apply_case(
+            message.summary.trim().trim_end_matches('.'),
+            self.subject_case,
+        );
+
+        let summary = render_pattern(
+            &self.summary_pattern,
+            &[
+                ("type", &commit_type),
+                ("scope", scope),
+                ("breaking", breaking),
+                ("emoji", emoji),
+                ("subject", &subject),
+            ],
+        );
+        let mut formatted = match self.max_summary_length {
+            Some(max) => shorten(&summary, max),
+            None => summary,
+        };
+
+        if let Some(description) = &message.description {
+            let description = match self.wrap_body {
+                Some(width) => wrap(description, width),
+                None => description.clone(),
+            };
+            formatted.push_str(&format!("\n\n{}", description));
+        }
+
+        if let (Some(token), Some(breaking)) = (&self.breaking_trailer, breaking_change) {
+            formatted = trailers::append(&formatted, &[Trailer::new(token, breaking)]);
+        }
+
+        formatted
+    }
+
+    /// Shorten the summary line of a formatted message to the maximum length again, after
+    /// something was added to it.
+    pub fn fit_summary(&self, message: &str) -> String {
+        let Some(max) = self.max_summary_length else {
+            return message.to_string();
+        };
+
+        match message.split_once('\n') {
+            Some((summary, rest)) => format!("{}\n{}", shorten(summary, max), rest),
+            None => shorten(message, max),
+        }
+    }
+}
+
+/// The type used when the model returns none or one the style does not allow.
+fn fallback_type(types: &[String]) -> String {
+    types
+        .iter()
+        .find(|commit_type| *commit_type == "chore")
+        .or(types.first())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Substitute `{name}` placeholders, dropping `[...]` groups that contain an empty placeholder.
+fn render_pattern(pattern: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::new();
+    let mut group: Option<(String, bool)> = None;
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' | ']' if chars.next_if_eq(&c).is_some() => match &mut group {
+                Some((text, _)) => text.push(c),
+                None => output.push(c),
+            },
+            '[' if group.is_none() => group = Some((String::new(), true)),
+            ']' if group.is_some() => {
+                if let Some((text, complete)) = group.take()
+                    && complete
+                {
+                    output.push_str(&text);
+                }
+            }
+            '{' => {
+                let name = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                let value = values
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| *value)
+                    .unwrap_or_default();
+                match &mut group {
+                    Some((text, complete)) => {
+                        text.push_str(value);
+                        *complete &= !value.is_empty();
+                    }
+                    None => output.push_str(value),
+                }
+            }
+            c => match &mut group {
+                Some((text, _)) => text.push(c),
+                None => output.push(c),
+            },
+        }
+    }
+
+    output.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn apply_case(subject: &str, case: SubjectCase) -> String {
+    let mut chars = subject.chars();
+    match (chars.next(), case) {
+        (Some(first), SubjectCase::Lower) => first.to_lowercase().chain(chars).collect(),
+        (Some(first), SubjectCase::Upper) => first.to_uppercase().chain(chars).collect(),
+        _ => subject.to_string(),
+    }
+}
+
+/// Cut the line at the last word boundary that keeps it within `max` characters.
+fn shorten(line: &str, max: usize) -> String {
+    if line.chars().count() <= max {
+        return line.to_string();
+    }
+
+    warn!("Summary is longer than {} characters, shortening it", max);
+    let mut shortened = String::new();
+    for word in line.split(' ') {
+        let candidate = match shortened.is_empty() {
+            true => word.to_string(),
+            false => format!("{} {}", shortened, word),
+        };
+        if candidate.chars().count() > max {
+            break;
+        }
+        shortened = candidate;
+    }
+
+    match shortened.is_empty() {
+        true => line.chars().take(max).collect(),
+        false => shortened,
+    }
+}
+
+/// Wrap every line at the given width, indenting continuation lines of list items.
+fn wrap(text: &str, width: usize) -> String {
+    let mut wrapped = Vec::new();
+
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        let mut indent = line[..line.len() - trimmed.len()].to_string();
+        let continuation = match trimmed.starts_with("- ") || trimmed.starts_with("* ") {
+            true => format!("{}  ", indent),
+            false => indent.clone(),
+        };
+
+        let mut current = String::new();
+        for word in trimmed.split_whitespace() {
+            let length = indent.len() + current.chars().count() + 1 + word.chars().count();
+            if !current.is_empty() && length > width {
+                wrapped.push(format!("{}{}", indent, current));
+                indent = continuation.clone();
+                current.clear();
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        wrapped.push(format!("{}{}", indent, current));
+    }
+
+    wrapped.join("\n")
+}
+
+/// Derive a scope from the changed paths.
+///
+/// Paths are matched against the configured map by their longest prefix. When no map entry
+/// applies, the first directory shared by all files (below `src/`, `packages/` and the like)
+/// is suggested instead.
+fn infer_scope(change: &StagedChange, scopes: &BTreeMap<String, String>) -> Option<InferredScope> {
+    let paths = change
+        .files
+        .iter()
+        .map(|file| file.path())
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        return None;
+    }
+
+    let configured = paths
+        .iter()
+        .map(|path| configured_scope(path, scopes))
+        .collect::<Option<Vec<_>>>();
+    if let Some(configured) = configured
+        && configured.iter().all(|scope| *scope == configured[0])
+    {
+        return Some(InferredScope::Configured(configured[0].to_string()));
+    }
+
+    let directories = paths
+        .iter()
+        .map(|path| component_directory(path))
+        .collect::<Option<Vec<_>>>()?;
+    directories
+        .iter()
+        .all(|directory| *directory == directories[0])
+        .then(|| InferredScope::Directory(directories[0].to_lowercase()))
+}
+
+/// Find the scope of the longest configured path prefix that contains the path.
+fn configured_scope<'a>(path: &Path, scopes: &'a BTreeMap<String, String>) -> Option<&'a str> {
+    scopes
+        .iter()
+        .filter(|(prefix, _)| path.starts_with(prefix.trim_end_matches('/')))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, scope)| scope.as_str())
+}
+
+/// The first directory of the path that names a component, skipping container directories.
+fn component_directory(path: &Path) -> Option<&str> {
+    path.parent()?
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .find(|directory| !CONTAINER_DIRS.contains(directory))
+}
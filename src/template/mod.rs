@@ -0,0 +1,230 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+/// Names of the built-in templates.
+pub const TEMPLATE_NAMES: [&str; 4] = ["commit", "review", "pr", "changelog"];
+
+/// The version of the built-in templates, bumped whenever their wording changes.
+const BUILT_IN_VERSION: &str = "2";
+
+/// A prompt template: a system prompt and a user message, both rendered from a context.
+///
+/// Templates support `{{name}}` placeholders, `{{#if name}}...{{/if}}` blocks that are only
+/// rendered when the value is set and not empty, and `{{> template}}` to include the same part
+/// of another template.
+#[derive(Debug, Clone)]
+pub struct Template {
+    pub name: String,
+    pub version: String,
+    system: String,
+    user: String,
+}
+
+/// An override of a built-in template, read from a TOML file.
+/// Parts that are left out keep the built-in text.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateFile {
+    version: String,
+    system: Option<String>,
+    user: Option<String>,
+}
+
+/// The values available to a template.
+#[derive(Debug, Default)]
+pub struct Context {
+    values: HashMap<String, String>,
+}
+
+impl Context {
+    pub fn set(&mut self, name: &str, value: impl Into<String>) -> &mut Self {
+        self.values.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Set the value only if there is one, so `{{#if name}}` blocks are skipped otherwise.
+    pub fn set_opt(&mut self, name: &str, value: Option<&str>) -> &mut Self {
+        if let Some(value) = value {
+            self.set(name, value);
+        }
+        self
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        self.values.get(name).is_some_and(|value| !value.is_empty())
+    }
+}
+
+impl Template {
+    /// Load a template, using the override file configured for its name if there is one.
+    /// Relative override paths are resolved against the config directory.
+    pub fn load(
+        name: &str,
+        overrides: &BTreeMap<String, PathBuf>,
+        config_dir: Option<&Path>,
+    ) -> Result<Self, String> {
+        Self::load_included(name, overrides, config_dir, &mut Vec::new())
+    }
+
+    /// Load a template included by the templates in `including`, which would never finish when
+    /// it includes one of them in turn.
+    fn load_included<'a>(
+        name: &'a str,
+        overrides: &BTreeMap<String, PathBuf>,
+        config_dir: Option<&Path>,
+        including: &mut Vec<&'a str>,
+    ) -> Result<Self, String> {
+        if including.contains(&name) {
+            return Err(format!(
+                "The {} template includes itself: {} > {}",
+                name,
+                including.join(" > "),
+                name
+            ));
+        }
+
+        let mut template = Self::built_in(name).ok_or_else(|| {
+            format!(
+                "Unknown template '{}', expected one of {}",
+                name,
+                TEMPLATE_NAMES.join(", ")
+            )
+        })?;
+
+        if let Some(path) = overrides.get(name) {
+            let path = match config_dir {
+                Some(dir) if path.is_relative() => dir.join(path),
+                _ => path.clone(),
+            };
+            debug!("Loading {} template from {}", name, path.display());
+
+            let content = fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read template {}: {}", path.display(), e))?;
+            let file = toml::from_str::<TemplateFile>(&content)
+                .map_err(|e| format!("Invalid template {}: {}", path.display(), e))?;
+
+            template.version = file.version;
+            if let Some(system) = file.system {
+                template.system = system.trim_end().to_string();
+            }
+            if let Some(user) = file.user {
+                template.user = user.trim_end().to_string();
+            }
+        }
+
+        // Resolve includes of other templates, which use their own overrides
+        including.push(name);
+        for other in TEMPLATE_NAMES {
+            let tag = format!("{{{{> {}}}}}", other);
+            if template.system.contains(&tag) || template.user.contains(&tag) {
+                let included = Self::load_included(other, overrides, config_dir, including)?;
+                template.system = template.system.replace(&tag, &included.system);
+                template.user = template.user.replace(&tag, &included.user);
+            }
+        }
+        including.pop();
+
+        Ok(template)
+    }
+
+    fn built_in(name: &str) -> Option<Self> {
+        let (system, user) = match name {
+            "commit" => (
+                include_str!("builtin/commit.system.md"),
+                include_str!("builtin/commit.user.md"),
+            ),
+            "review" => (
+                include_str!("builtin/review.system.md"),
+                include_str!("builtin/review.user.md"),
+            ),
+            "pr" => (
+                include_str!("builtin/pr.system.md"),
+                include_str!("builtin/pr.user.md"),
+            ),
+            "changelog" => (
+                include_str!("builtin/changelog.system.md"),
+                include_str!("builtin/changelog.user.md"),
+            ),
+            _ => return None,
+        };
+
+        Some(Template {
+            name: name.to_string(),
+            version: BUILT_IN_VERSION.to_string(),
+            system: system.trim_end().to_string(),
+            user: user.trim_end().to_string(),
+        })
+    }
+
+    /// Render the system prompt.
+    pub fn system(&self, context: &Context) -> String {
+        render(&self.system, context)
+    }
+
+    /// Render the user message.
+    pub fn user(&self, context: &Context) -> String {
+        render(&self.user, context)
+    }
+}
+
+/// Render the template text in a single pass, so placeholders inside values are left alone.
+pub fn render(text: &str, context: &Context) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 2..end].trim();
+        let tail = &rest[end + 2..];
+
+        if let Some(name) = tag.strip_prefix("#if ") {
+            let (body, remainder) = split_block(tail);
+            if context.is_set(name.trim()) {
+                output.push_str(&render(body, context));
+            }
+            rest = remainder;
+        } else {
+            match context.values.get(tag) {
+                Some(value) => output.push_str(value),
+                None => {
+                    warn!("Unknown template variable '{}'", tag);
+                    output.push_str(&rest[start..end + 2]);
+                }
+            }
+            rest = tail;
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Split the text after an `{{#if}}` tag into the block body and the text after its `{{/if}}`.
+fn split_block(text: &str) -> (&str, &str) {
+    let mut depth = 0;
+    let mut position = 0;
+
+    while let Some(start) = text[position..].find("{{").map(|start| position + start) {
+        let Some(end) = text[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        match text[start + 2..end].trim() {
+            "/if" if depth == 0 => return (&text[..start], &text[end + 2..]),
+            "/if" => depth -= 1,
+            tag if tag.starts_with("#if ") => depth += 1,
+            _ => {}
+        }
+        position = end + 2;
+    }
+
+    warn!("Template block is missing its {{{{/if}}}}");
+    (text, "")
+}
@@ -0,0 +1,124 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::git;
+use crate::style::MessageStyle;
+
+/// Matches JIRA and Linear style ticket keys, e.g. `PROJ-123` or `ENG-42`.
+const DEFAULT_PATTERN: &str = r"[A-Z][A-Z0-9]+-[0-9]+";
+
+/// Where the detected ticket reference is added to the commit message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TicketPlacement {
+    /// Before the summary line, e.g. `PROJ-123 Add password reset flow`, or after the type of
+    /// type-prefixed styles, e.g. `feat(auth): PROJ-123 add password reset flow`.
+    #[default]
+    Prefix,
+    /// As a `Refs: PROJ-123` trailer.
+    #[serde(alias = "trailer")]
+    Refs,
+    /// As a `Closes: PROJ-123` trailer.
+    Closes,
+    /// Detect the ticket for prompts, but leave the message alone.
+    None,
+}
+
+/// How ticket references are detected and added to commit messages.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TicketConfig {
+    /// The regex matching a ticket in the branch name or user description. When it contains a
+    /// capture group, the first group is the ticket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// How the ticket is written in the message, where `{ticket}` is the match, e.g. `#{ticket}`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    pub placement: TicketPlacement,
+}
+
+impl TicketConfig {
+    /// Check that the configured pattern is a valid regex.
+    pub fn validate(&self) -> Result<(), String> {
+        self.regex().map(|_| ())
+    }
+
+    fn regex(&self) -> Result<Regex, String> {
+        let pattern = self.pattern.as_deref().unwrap_or(DEFAULT_PATTERN);
+
+        Regex::new(pattern).map_err(|e| format!("Invalid ticket pattern '{}': {}", pattern, e))
+    }
+}
+
+/// Find the ticket reference of the current change, from the branch name first and the user
+/// description second.
+pub fn detect(config: &TicketConfig, user_description: Option<&str>) -> Option<String> {
+    let regex = match config.regex() {
+        Ok(regex) => regex,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+    let branch = git::current_branch().ok().flatten();
+
+    let ticket = [branch.as_deref(), user_description]
+        .into_iter()
+        .flatten()
+        .find_map(|text| {
+            regex.captures(text).and_then(|captures| {
+                captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map(|ticket| ticket.as_str().to_string())
+            })
+        })?;
+    let reference = config
+        .format
+        .as_deref()
+        .unwrap_or("{ticket}")
+        .replace("{ticket}", &ticket);
+    debug!("Detected ticket {}", reference);
+
+    Some(reference)
+}
+
+/// Prefix the summary of a formatted commit message with the ticket reference, unless the
+/// message already mentions it. Trailer placements are handled by [`crate::trailers::collect`].
+///
+/// The prefix follows the shape learned from the repository history, e.g. `[ABC-123] `, and
+/// falls back to the reference followed by a space. Styles whose summary starts with a type,
+/// such as `feat(api): `, keep the type first and get the reference after it. The summary is
+/// shortened again to the style's maximum length.
+pub fn apply(
+    message: &str,
+    style: &MessageStyle,
+    reference: &str,
+    placement: TicketPlacement,
+    history_shape: Option<&str>,
+) -> String {
+    if placement != TicketPlacement::Prefix || message.contains(reference) {
+        return message.to_string();
+    }
+
+    let prefix = history_shape
+        .map(|shape| shape.replace("ABC-123", reference))
+        .unwrap_or_else(|| format!("{} ", reference));
+    let position = match style.uses_type() {
+        true => message
+            .lines()
+            .next()
+            .and_then(|summary| summary.find(": "))
+            .map_or(0, |end| end + 2),
+        false => 0,
+    };
+
+    style.fit_summary(&format!(
+        "{}{}{}",
+        &message[..position],
+        prefix,
+        &message[position..]
+    ))
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use crate::config_manager::AppConfig;
+use crate::git;
+use crate::style::MessageStyle;
+use crate::ticket::TicketPlacement;
+
+/// Footer tokens that contain a space but are still part of the trailer block,
+/// as defined by the Conventional Commits specification.
+const SPECIAL_TOKENS: [&str; 1] = ["BREAKING CHANGE"];
+
+/// A `Key: value` line at the end of a commit message, as understood by `git interpret-trailers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+impl Trailer {
+    pub fn new(key: &str, value: &str) -> Self {
+        Trailer {
+            key: key.to_string(),
+            value: value.trim().to_string(),
+        }
+    }
+
+    /// Parse a `Key: value` line. Keys consist of letters, digits and dashes.
+    fn parse(line: &str) -> Option<Self> {
+        let (key, value) = line.split_once(':')?;
+        (is_valid_key(key) || SPECIAL_TOKENS.contains(&key)).then(|| Trailer::new(key, value))
+    }
+
+    fn matches(&self, other: &Trailer) -> bool {
+        self.key.eq_ignore_ascii_case(&other.key) && self.value == other.value
+    }
+}
+
+/// Whether the key is a valid trailer token.
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Collect the trailers for a new message, in the order git conventionally lists them:
+/// ticket references, custom trailers, co-authors and the sign-off last.
+pub fn collect(
+    config: &AppConfig,
+    style: &MessageStyle,
+    ticket: Option<&str>,
+) -> Result<Vec<Trailer>, String> {
+    let mut trailers = Vec::new();
+
+    match (config.ticket.placement, ticket) {
+        (TicketPlacement::Refs, Some(ticket)) => trailers.push(Trailer::new("Refs", ticket)),
+        (TicketPlacement::Closes, Some(ticket)) => trailers.push(Trailer::new("Closes", ticket)),
+        _ => {}
+    }
+
+    for (key, value) in &config.trailers {
+        trailers.push(Trailer::new(key, value));
+    }
+
+    for co_author in &config.co_authors {
+        trailers.push(Trailer::new(
+            "Co-authored-by",
+            &resolve_co_author(co_author, &config.roster)?,
+        ));
+    }
+
+    if config.signoff || style.signoff {
+        let identity = git::identity().map_err(|e| format!("Cannot sign off: {}", e))?;
+        trailers.push(Trailer::new("Signed-off-by", &identity));
+    }
+
+    Ok(trailers)
+}
+
+/// Resolve a co-author alias from the roster. Values that already look like
+/// `Name <email>` are used as they are.
+fn resolve_co_author(co_author: &str, roster: &BTreeMap<String, String>) -> Result<String, String> {
+    if let Some(identity) = roster.get(co_author) {
+        return Ok(identity.clone());
+    }
+    if co_author.contains('<') && co_author.ends_with('>') {
+        return Ok(co_author.to_string());
+    }
+
+    match roster.is_empty() {
+        true => Err(format!(
+            "Unknown co-author '{}'. Use 'Name <email>' or add an alias to the roster in the config file",
+            co_author
+        )),
+        false => Err(format!(
+            "Unknown co-author '{}', expected 'Name <email>' or one of {}",
+            co_author,
+            roster.keys().cloned().collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// Append trailers to a message following the rules of `git interpret-trailers`: they join an
+/// existing trailer block in the last paragraph, or start a new paragraph otherwise. Trailers
+/// that are already present with the same value are not repeated.
+pub fn append(message: &str, trailers: &[Trailer]) -> String {
+    let message = message.trim_end();
+    let (body, mut block) = match split_trailer_block(message) {
+        Some((body, block)) => (body, block),
+        None => (message, Vec::new()),
+    };
+
+    let mut added = false;
+    for trailer in trailers {
+        if !block.iter().any(|existing| existing.matches(trailer)) {
+            block.push(trailer.clone());
+            added = true;
+        }
+    }
+    if !added {
+        return message.to_string();
+    }
+
+    let block = block
+        .iter()
+        .map(|trailer| format!("{}: {}", trailer.key, trailer.value))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    match body.is_empty() {
+        true => block,
+        false => format!("{}\n\n{}", body, block),
+    }
+}
+
+/// Split off the last paragraph if it consists only of trailers and their continuation lines.
+fn split_trailer_block(message: &str) -> Option<(&str, Vec<Trailer>)> {
+    // The summary line is never a trailer block, even if it looks like one
+    let (body, last) = message.rsplit_once("\n\n")?;
+
+    let mut block: Vec<Trailer> = Vec::new();
+    for line in last.lines() {
+        match (line.starts_with([' ', '\t']), block.last_mut()) {
+            (true, Some(previous)) => {
+                previous.value = format!("{}\n{}", previous.value, line);
+            }
+            _ => block.push(Trailer::parse(line)?),
+        }
+    }
+
+    Some((body.trim_end(), block))
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use dialoguer::console::{Style, Term};
+use log::debug;
+use serde::{Deserialize, Deserializer};
+
+use crate::diff::{FileChange, LineKind, StagedChange};
+
+/// What kind of sensitive information a warning is about.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// Passwords, tokens, keys and other credentials.
+    Secret,
+    /// Email addresses, phone numbers, bank details and other personal data.
+    PersonalData,
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[serde(alias = "info")]
+    Low,
+    #[default]
+    #[serde(alias = "moderate")]
+    Medium,
+    #[serde(alias = "critical")]
+    High,
+}
+
+/// Possible sensitive information the model found in the changes.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Warning {
+    /// The path of the file, as shown in the diff.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// The line number in the new version of the file.
+    #[serde(default)]
+    pub line: Option<u32>,
+    #[serde(default)]
+    pub kind: WarningKind,
+    #[serde(default)]
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Warning {
+    fn from_message(message: String) -> Self {
+        Warning {
+            file: None,
+            line: None,
+            kind: WarningKind::default(),
+            severity: Severity::default(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        };
+
+        let kind = match self.kind {
+            WarningKind::Secret => " secret",
+            WarningKind::PersonalData => " personal data",
+            WarningKind::Other => "",
+        };
+
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => {
+                write!(
+                    f,
+                    "[{}{}] {}:{}: {}",
+                    severity, kind, file, line, self.message
+                )
+            }
+            (Some(file), None) => write!(f, "[{}{}] {}: {}", severity, kind, file, self.message),
+            _ => write!(f, "[{}{}] {}", severity, kind, self.message),
+        }
+    }
+}
+
+/// Deserialize the warnings of a commit message. Besides a list of warning objects, a single
+/// string or a list of strings is accepted, as returned for templates written before warnings
+/// were structured.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Warning>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Item {
+        Text(String),
+        Structured(Warning),
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Warnings {
+        One(Item),
+        Many(Vec<Item>),
+    }
+
+    let items = match Option::<Warnings>::deserialize(deserializer)? {
+        None => Vec::new(),
+        Some(Warnings::One(item)) => vec![item],
+        Some(Warnings::Many(items)) => items,
+    };
+
+    Ok(items
+        .into_iter()
+        .map(|item| match item {
+            Item::Text(message) => Warning::from_message(message),
+            Item::Structured(warning) => warning,
+        })
+        .filter(|warning| !warning.message.trim().is_empty())
+        .collect())
+}
+
+/// Check the locations reported by the model against the changes, so they point at files in the
+/// diff and at lines that were actually added. Line numbers inside a hunk are moved to the nearest
+/// added line of that hunk, other unknown locations are dropped.
+pub fn verify(warnings: Vec<Warning>, change: &StagedChange) -> Vec<Warning> {
+    warnings
+        .into_iter()
+        .map(|mut warning| {
+            let Some(reported) = warning.file.clone() else {
+                warning.line = None;
+                return warning;
+            };
+
+            match find_file(change, &reported) {
+                Some(file) => {
+                    warning.file = Some(file.path().display().to_string());
+                    if let Some(line) = warning.line {
+                        warning.line = nearest_added_line(file, line);
+                        if warning.line != Some(line) {
+                            debug!(
+                                "Warning line {} of {} is not an added line, using {:?}",
+                                line, reported, warning.line
+                            );
+                        }
+                    }
+                }
+                None => {
+                    debug!("Warning refers to {}, which is not in the diff", reported);
+                    warning.file = None;
+                    warning.line = None;
+                }
+            }
+
+            warning
+        })
+        .collect()
+}
+
+/// Find the file by its path, ignoring `a/` and `b/` prefixes. A shorter path matches if it is
+/// the suffix of exactly one changed file.
+fn find_file<'a>(change: &'a StagedChange, reported: &str) -> Option<&'a FileChange> {
+    let reported = reported
+        .strip_prefix("b/")
+        .or_else(|| reported.strip_prefix("a/"))
+        .unwrap_or(reported);
+    let reported = Path::new(reported);
+
+    if let Some(file) = change.files.iter().find(|file| file.path() == reported) {
+        return Some(file);
+    }
+
+    let mut candidates = change
+        .files
+        .iter()
+        .filter(|file| file.path().ends_with(reported));
+    match (candidates.next(), candidates.next()) {
+        (Some(file), None) => Some(file),
+        _ => None,
+    }
+}
+
+/// The added line closest to the reported one within the hunk that contains it.
+fn nearest_added_line(file: &FileChange, line: u32) -> Option<u32> {
+    let hunk = file
+        .hunks
+        .iter()
+        .find(|hunk| line >= hunk.new_start && line < hunk.new_start + hunk.new_lines.max(1))?;
+
+    hunk.lines
+        .iter()
+        .filter(|diff_line| diff_line.kind == LineKind::Addition)
+        .filter_map(|diff_line| diff_line.new_lineno)
+        .min_by_key(|added| added.abs_diff(line))
+}
+
+/// Print the warnings, the most severe first.
+pub fn print(terminal: &Term, warnings: &[Warning]) -> io::Result<()> {
+    let mut warnings = warnings.iter().collect::<Vec<_>>();
+    warnings.sort_by_key(|warning| std::cmp::Reverse(warning.severity));
+
+    for warning in warnings {
+        let style = match warning.severity {
+            Severity::High => Style::new().white().bold().on_red(),
+            Severity::Medium => Style::new().red().bold(),
+            Severity::Low => Style::new().yellow(),
+        };
+        terminal.write_line(&style.apply_to(warning).to_string())?;
+    }
+
+    Ok(())
+}